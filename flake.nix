@@ -38,7 +38,7 @@
             ];
 
             src = ./.;
-            cargoSha256 = "lJG+nj9i1bxjjN+/tQfsRZ2HYsFzmRheEJyEzrXiOZM=";
+            cargoLock.lockFile = ./Cargo.lock;
           };
           
     });
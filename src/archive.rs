@@ -0,0 +1,235 @@
+use std::fs::{self, create_dir_all, File};
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use glob::Pattern;
+
+use crate::backend::is_font_file;
+use crate::{Error, Result};
+
+fn url_path(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or(url)
+}
+
+pub(crate) fn is_archive(url: &str) -> bool {
+    let path = url_path(url).to_lowercase();
+    path.ends_with(".zip")
+        || path.ends_with(".tar.gz")
+        || path.ends_with(".tgz")
+        || path.ends_with(".tar")
+}
+
+/// Extracts the font files (.ttf, .otf, .ttc) found in the archive into `dir`
+/// and returns their paths.
+pub(crate) fn extract_fonts(url: &str, data: &[u8], dir: &Path) -> Result<Vec<PathBuf>> {
+    let path = url_path(url).to_lowercase();
+    create_dir_all(dir)?;
+    let mut members = if path.ends_with(".zip") {
+        extract_zip(data, dir)?
+    } else if path.ends_with(".tar") {
+        extract_tar(tar::Archive::new(Cursor::new(data)), dir)?
+    } else {
+        extract_tar(tar::Archive::new(GzDecoder::new(Cursor::new(data))), dir)?
+    };
+    members.sort();
+    Ok(members)
+}
+
+fn extract_zip(data: &[u8], dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(data)).map_err(|e| Error::Parse(e.to_string()))?;
+    let mut members = Vec::new();
+    for i in 0..archive.len() {
+        let mut member = archive.by_index(i).map_err(|e| Error::Parse(e.to_string()))?;
+        let name = match member.enclosed_name() {
+            Some(name) if member.is_file() && is_font_file(name) => name.to_path_buf(),
+            _ => continue,
+        };
+        let output_path = dir.join(&name);
+        if let Some(parent) = output_path.parent() {
+            create_dir_all(parent)?;
+        }
+        io::copy(&mut member, &mut File::create(&output_path)?)?;
+        members.push(output_path);
+    }
+    Ok(members)
+}
+
+fn extract_tar<R: io::Read>(mut archive: tar::Archive<R>, dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut members = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_path_buf();
+        if !entry.header().entry_type().is_file() || !is_font_file(&name) {
+            continue;
+        }
+        if entry.unpack_in(dir)? {
+            members.push(dir.join(name));
+        }
+    }
+    Ok(members)
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Names a variant is commonly spelled as in font file names, `700` -> `bold`...
+fn variant_aliases(variant: &str) -> Vec<String> {
+    let variant = normalize(variant);
+    let (weight, italic) = match variant.strip_suffix("italic") {
+        Some(weight) => (weight.to_string(), true),
+        None => (variant.clone(), false),
+    };
+    let weight_names: &[&str] = match weight.as_str() {
+        "100" => &["thin", "hairline"],
+        "200" => &["extralight", "ultralight"],
+        "300" => &["light"],
+        "" | "400" | "regular" => &["regular", "normal", "book", ""],
+        "500" => &["medium"],
+        "600" => &["semibold", "demibold"],
+        "700" => &["bold"],
+        "800" => &["extrabold", "ultrabold"],
+        "900" => &["black", "heavy"],
+        _ => &[],
+    };
+
+    let mut aliases = vec![variant.clone()];
+    for name in weight_names.iter().chain([weight.as_str()].iter()) {
+        if italic {
+            aliases.push(format!("{}italic", name));
+            if name.is_empty() || *name == "regular" {
+                aliases.push("italic".to_string());
+            }
+        } else {
+            aliases.push(name.to_string());
+        }
+    }
+    aliases.dedup();
+    aliases
+}
+
+/// Picks the archive member holding `variant`, either with the glob `pattern`
+/// from the catalog or by matching the variant against the member file names.
+pub(crate) fn find_member(
+    members: &[PathBuf],
+    dir: &Path,
+    family: &str,
+    variant: &str,
+    pattern: Option<&str>,
+) -> Result<Option<PathBuf>> {
+    if let Some(pattern) = pattern {
+        let pattern = Pattern::new(pattern).map_err(|e| Error::Parse(e.to_string()))?;
+        return Ok(members
+            .iter()
+            .find(|member| {
+                member
+                    .strip_prefix(dir)
+                    .is_ok_and(|relative| pattern.matches_path(relative))
+                    || member
+                        .file_name()
+                        .is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+            })
+            .cloned());
+    }
+
+    let family = normalize(family);
+    let aliases = variant_aliases(variant);
+    Ok(members
+        .iter()
+        .find(|member| {
+            let stem = match member.file_stem() {
+                Some(stem) => normalize(&stem.to_string_lossy()),
+                None => return false,
+            };
+            let style = stem.strip_prefix(&family).unwrap_or(&stem);
+            aliases.iter().any(|alias| alias == style)
+        })
+        .cloned())
+}
+
+/// Lowercase extension of `path`, `ttf` when it has none or one that isn't
+/// plain letters and digits (a glob, say).
+pub(crate) fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .filter(|extension| !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or_else(|| "ttf".to_string())
+}
+
+/// Extension of the file at `url`, ignoring its query and fragment.
+pub(crate) fn url_extension(url: &str) -> String {
+    extension(Path::new(url_path(url)))
+}
+
+/// `part` made safe to use in a file name: path separators and `..` from a
+/// catalog can't lead outside the folder the file is written to.
+pub(crate) fn file_name_part(part: &str) -> String {
+    part.replace(['/', '\\', '\0'], "_").replace("..", "_")
+}
+
+pub(crate) fn copy_member(member: &Path, output_file: &Path) -> Result<()> {
+    if let Some(parent) = output_file.parent() {
+        create_dir_all(parent).map_err(|e| Error::with_path(e, parent.to_path_buf()))?;
+    }
+    fs::copy(member, output_file).map_err(|e| Error::with_path(e, output_file.to_path_buf()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(dir: &Path) -> Vec<PathBuf> {
+        ["Family-Regular.ttf", "Family-Bold.ttf", "Family-BoldItalic.ttf"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect()
+    }
+
+    #[test]
+    fn variant_aliases_name_weights() {
+        assert!(variant_aliases("700").contains(&"bold".to_string()));
+        assert!(variant_aliases("700italic").contains(&"bolditalic".to_string()));
+        assert!(variant_aliases("italic").contains(&"italic".to_string()));
+        assert!(variant_aliases("regular").contains(&"regular".to_string()));
+    }
+
+    #[test]
+    fn find_member_matches_variant_names() {
+        let dir = Path::new("/archive");
+        let members = members(dir);
+        let find = |variant| find_member(&members, dir, "Family", variant, None).unwrap();
+        assert_eq!(find("700"), Some(dir.join("Family-Bold.ttf")));
+        assert_eq!(find("700italic"), Some(dir.join("Family-BoldItalic.ttf")));
+        assert_eq!(find("regular"), Some(dir.join("Family-Regular.ttf")));
+        assert_eq!(find("300"), None);
+    }
+
+    #[test]
+    fn find_member_uses_pattern() {
+        let dir = Path::new("/archive");
+        let members = members(dir);
+        let found = find_member(&members, dir, "Family", "700", Some("*BoldItalic.ttf")).unwrap();
+        assert_eq!(found, Some(dir.join("Family-BoldItalic.ttf")));
+    }
+
+    #[test]
+    fn url_extension_ignores_query_and_odd_suffixes() {
+        assert_eq!(url_extension("https://host/Font.OTF?raw=1#top"), "otf");
+        assert_eq!(url_extension("https://host/download?id=1.woff2"), "ttf");
+        assert_eq!(url_extension("https://host/Font.t/../x"), "ttf");
+        assert_eq!(extension(Path::new("fonts/*")), "ttf");
+    }
+
+    #[test]
+    fn file_name_part_stays_in_folder() {
+        assert_eq!(file_name_part("../../etc/x"), "____etc_x");
+        assert_eq!(file_name_part("a\\b"), "a_b");
+        assert_eq!(file_name_part("Noto Sans"), "Noto Sans");
+    }
+}
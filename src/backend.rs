@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+
+use font_kit::font::Font as LoadedFont;
+use font_kit::properties::Style;
+
+use crate::{
+    fetch_repo_font_list_if_changed, trusted_keys_dir, Error, RepoFont, Repository, Result,
+    Validators, Verify,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepositoryMetadata {
+    pub name: String,
+    pub kind: String,
+    pub url: String,
+}
+
+/// A source of font catalogs. The `type` key of a repository in `repos.conf`
+/// selects which backend is used to read it.
+pub trait RepositoryBackend {
+    /// Value of the `type` key that selects this backend.
+    fn kind(&self) -> &'static str;
+
+    /// Fetches every font the repository offers.
+    fn fetch_catalog(&self, repo: &Repository) -> Result<Vec<RepoFont>>;
+
+    /// Fetches the catalog unless it hasn't changed since the response
+    /// described by `cached`, returning `None` in that case. Backends that
+    /// can't tell always fetch it.
+    fn fetch_catalog_if_changed(
+        &self,
+        repo: &Repository,
+        _cached: &Validators,
+    ) -> Result<Option<(Vec<RepoFont>, Validators)>> {
+        Ok(Some((self.fetch_catalog(repo)?, Validators::default())))
+    }
+
+    /// Returns the URL each variant of `font` can be downloaded from.
+    fn resolve_download_urls(
+        &self,
+        _repo: &Repository,
+        font: &RepoFont,
+    ) -> Result<HashMap<String, String>> {
+        Ok(font.files.clone())
+    }
+
+    fn metadata(&self, repo: &Repository) -> RepositoryMetadata {
+        RepositoryMetadata {
+            name: repo.name.clone(),
+            kind: self.kind().to_string(),
+            url: repo.url.clone(),
+        }
+    }
+}
+
+/// Catalogs in the `webfonts#webfontList` JSON shape used by the Google Fonts API.
+pub struct WebfontsBackend;
+
+impl RepositoryBackend for WebfontsBackend {
+    fn kind(&self) -> &'static str {
+        "webfonts"
+    }
+
+    fn fetch_catalog(&self, repo: &Repository) -> Result<Vec<RepoFont>> {
+        Ok(self
+            .fetch_catalog_if_changed(repo, &Validators::default())?
+            .map(|(fonts, _validators)| fonts)
+            .unwrap_or_default())
+    }
+
+    fn fetch_catalog_if_changed(
+        &self,
+        repo: &Repository,
+        cached: &Validators,
+    ) -> Result<Option<(Vec<RepoFont>, Validators)>> {
+        let keys_dir = trusted_keys_dir()
+            .ok_or_else(|| Error::NotFound("trusted keys directory".to_string()))?;
+        fetch_repo_font_list_if_changed(
+            &repo.url,
+            repo.key.clone(),
+            repo.verify,
+            &keys_dir,
+            &repo.http,
+            cached,
+        )
+    }
+}
+
+/// Fonts stored in a local directory (or a `file://` URL), scanned with font-kit.
+/// Fonts from these repositories are installed by copying the files.
+pub struct LocalBackend;
+
+impl RepositoryBackend for LocalBackend {
+    fn kind(&self) -> &'static str {
+        "local"
+    }
+
+    fn fetch_catalog(&self, repo: &Repository) -> Result<Vec<RepoFont>> {
+        if repo.verify == Verify::Required {
+            return Err(Error::InvalidSignature(format!(
+                "{} is a local repository and has no signed catalog",
+                repo.name
+            )));
+        }
+        let root = local_path_from_url(&repo.url);
+        if !root.is_dir() {
+            return Err(Error::NotFound(format!("directory {}", root.display())));
+        }
+
+        let mut paths = Vec::new();
+        collect_font_paths(&root, &mut paths)?;
+        paths.sort();
+
+        let mut fonts: HashMap<String, RepoFont> = HashMap::new();
+        for path in paths {
+            let loaded = match LoadedFont::from_path(&path, 0) {
+                Ok(loaded) => loaded,
+                Err(_) => continue,
+            };
+            let family = loaded.family_name();
+            let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+
+            let font = fonts.entry(family.clone()).or_insert(RepoFont {
+                kind: Some("webfonts#webfont".to_string()),
+                family: Some(family),
+                category: loaded.is_monospace().then(|| "monospace".to_string()),
+                ..RepoFont::default()
+            });
+
+            let mut variant = variant_from_properties(&loaded);
+            if font.files.contains_key(&variant) {
+                variant = loaded
+                    .postscript_name()
+                    .unwrap_or_else(|| path.display().to_string());
+            }
+            font.variants.push(variant.clone());
+            font.files.insert(variant, format!("file://{}", path.display()));
+
+            if let Some(modified) = modified {
+                let modified = DateTime::<Utc>::from(modified).format("%Y-%m-%d").to_string();
+                if font.last_modified.as_ref().is_none_or(|last| &modified > last) {
+                    font.last_modified = Some(modified);
+                }
+            }
+        }
+
+        let mut fonts: Vec<RepoFont> = fonts.into_values().collect();
+        fonts.sort_by(|a, b| a.family.cmp(&b.family));
+        Ok(fonts)
+    }
+}
+
+fn collect_font_paths(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_font_paths(&path, paths)?;
+        } else if is_font_file(&path) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn is_font_file(path: &Path) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => matches!(
+            extension.to_lowercase().as_str(),
+            "ttf" | "otf" | "ttc" | "otc"
+        ),
+        None => false,
+    }
+}
+
+/// Repository style variant name (`regular`, `700italic`...) of the font at `path`.
+pub(crate) fn variant_of_file(path: &Path) -> Option<String> {
+    LoadedFont::from_path(path, 0)
+        .ok()
+        .map(|font| variant_from_properties(&font))
+}
+
+/// Names a variant the way webfonts catalogs do: `regular`, `italic`, `700`, `700italic`...
+fn variant_from_properties(font: &LoadedFont) -> String {
+    let properties = font.properties();
+    let weight = properties.weight.0.round() as u32;
+    let italic = properties.style != Style::Normal;
+    match (weight, italic) {
+        (400, false) => "regular".to_string(),
+        (400, true) => "italic".to_string(),
+        (weight, false) => weight.to_string(),
+        (weight, true) => format!("{}italic", weight),
+    }
+}
+
+pub(crate) fn is_local_url(url: &str) -> bool {
+    url.starts_with("file://") || (!url.contains("://") && Path::new(url).is_absolute())
+}
+
+pub(crate) fn local_path_from_url(url: &str) -> PathBuf {
+    let path = url.strip_prefix("file://").unwrap_or(url);
+    #[cfg(target_os = "windows")]
+    let path = path.trim_start_matches('/');
+    PathBuf::from(path)
+}
+
+pub fn get_backend(repo: &Repository) -> Result<Box<dyn RepositoryBackend>> {
+    match repo.backend.as_deref() {
+        None if is_local_url(&repo.url) => Ok(Box::new(LocalBackend)),
+        Some("local") => Ok(Box::new(LocalBackend)),
+        None | Some("webfonts") | Some("google") => Ok(Box::new(WebfontsBackend)),
+        Some(kind) => Err(Error::NotFound(format!(
+            "repository type \"{}\" used by {}",
+            kind, repo.name
+        ))),
+    }
+}
+
+pub fn generate_repo_font_list_with_backend(
+    backend: &dyn RepositoryBackend,
+    repo: &Repository,
+) -> Result<Vec<RepoFont>> {
+    let mut fonts = backend.fetch_catalog(repo)?;
+    for font in fonts.iter_mut() {
+        font.files = backend.resolve_download_urls(repo, font)?;
+    }
+    Ok(fonts)
+}
+
+/// Like `generate_repo_font_list_with_backend`, but returns `None` when the
+/// catalog hasn't changed since the response described by `cached`.
+pub fn generate_repo_font_list_if_changed_with_backend(
+    backend: &dyn RepositoryBackend,
+    repo: &Repository,
+    cached: &Validators,
+) -> Result<Option<(Vec<RepoFont>, Validators)>> {
+    let (mut fonts, validators) = match backend.fetch_catalog_if_changed(repo, cached)? {
+        Some(response) => response,
+        None => return Ok(None),
+    };
+    for font in fonts.iter_mut() {
+        font.files = backend.resolve_download_urls(repo, font)?;
+    }
+    Ok(Some((fonts, validators)))
+}
+
+pub fn generate_repo_font_list(repo: &Repository) -> Result<Vec<RepoFont>> {
+    generate_repo_font_list_with_backend(get_backend(repo)?.as_ref(), repo)
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    font_catcher_dir, generate_repo_font_list_from_file,
+    generate_repo_font_list_if_changed_with_backend, get_backend, parallel, FontsList, RepoFont,
+    write_atomic, Error, Repository, Result, Validators,
+};
+
+/// Stored as `repos/<name>.meta` next to each cached catalog.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CacheMetadata {
+    #[serde(flatten)]
+    pub validators: Validators,
+    /// RFC 3339 time the catalog was last downloaded or confirmed unchanged.
+    #[serde(rename = "fetchedAt")]
+    pub fetched_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshStatus {
+    /// A new catalog was downloaded.
+    Updated,
+    /// The server confirmed that the cached catalog is current.
+    NotModified,
+    /// The cached catalog is younger than the repository's `max_age`, so
+    /// nothing was requested.
+    Fresh,
+}
+
+/// The cached catalogs read by `read_repo_caches`.
+#[derive(Debug, Default)]
+pub struct RepoCaches {
+    /// Catalogs keyed by repository name.
+    pub repos: HashMap<String, Vec<RepoFont>>,
+    /// Catalog files that couldn't be read, and why.
+    pub skipped: Vec<(PathBuf, Error)>,
+}
+
+pub fn repo_cache_dir() -> Option<PathBuf> {
+    font_catcher_dir().map(|dir| dir.join("repos"))
+}
+
+pub(crate) fn catalog_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.json", name))
+}
+
+fn metadata_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.meta", name))
+}
+
+fn is_fresh(repo: &Repository, metadata: &CacheMetadata) -> bool {
+    let max_age = match repo.max_age {
+        Some(max_age) => max_age,
+        None => return false,
+    };
+    DateTime::parse_from_rfc3339(&metadata.fetched_at)
+        .map(|fetched_at| {
+            Utc::now().signed_duration_since(fetched_at).num_seconds() < max_age as i64
+        })
+        .unwrap_or(false)
+}
+
+/// Brings the cached catalog of `repo` in `dir` up to date. Unless `force` is
+/// set, a catalog younger than the repository's `max_age` is left alone.
+/// Otherwise the ETag and Last-Modified of the previous response are sent
+/// along, so an unchanged catalog isn't downloaded again.
+pub fn refresh_repo_cache(repo: &Repository, dir: &Path, force: bool) -> Result<RefreshStatus> {
+    let catalog_file = catalog_path(dir, &repo.name);
+    let metadata_file = metadata_path(dir, &repo.name);
+    let metadata: Option<CacheMetadata> = match catalog_file.exists() {
+        true => fs::read_to_string(&metadata_file)
+            .ok()
+            .and_then(|metadata| serde_json::from_str(&metadata).ok()),
+        false => None,
+    };
+    if let Some(metadata) = &metadata {
+        if !force && is_fresh(repo, metadata) {
+            return Ok(RefreshStatus::Fresh);
+        }
+    }
+
+    let cached = metadata.map(|metadata| metadata.validators).unwrap_or_default();
+    let backend = get_backend(repo)?;
+    let (status, validators) =
+        match generate_repo_font_list_if_changed_with_backend(backend.as_ref(), repo, &cached)? {
+            Some((items, validators)) => {
+                create_dir_all(dir)?;
+                let catalog = FontsList {
+                    kind: "webfonts#webfontList".to_string(),
+                    items,
+                };
+                write_atomic(&catalog_file, serde_json::to_string_pretty(&catalog)?.as_bytes())?;
+                (RefreshStatus::Updated, validators)
+            }
+            None => (RefreshStatus::NotModified, cached),
+        };
+    let metadata = CacheMetadata {
+        validators,
+        fetched_at: Utc::now().to_rfc3339(),
+    };
+    write_atomic(&metadata_file, serde_json::to_string_pretty(&metadata)?.as_bytes())?;
+    Ok(status)
+}
+
+/// Refreshes the cached catalogs of `repos` concurrently, returning one
+/// result per repository in the same order.
+pub fn refresh_repo_caches(
+    repos: &[Repository],
+    dir: &Path,
+    force: bool,
+) -> Vec<Result<RefreshStatus>> {
+    parallel::map(repos, |repo| refresh_repo_cache(repo, dir, force))
+}
+
+/// Reads every cached catalog in `dir`. Catalogs that can't be read are
+/// skipped and listed in `RepoCaches::skipped`.
+pub fn read_repo_caches(dir: &Path) -> Result<RepoCaches> {
+    let mut caches = RepoCaches::default();
+    if !dir.is_dir() {
+        return Ok(caches);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let name = match path.file_stem() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        match generate_repo_font_list_from_file(&path) {
+            Ok(fonts_list) => {
+                caches.repos.insert(name, fonts_list);
+            }
+            Err(e) => caches.skipped.push((path, e)),
+        }
+    }
+    Ok(caches)
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use crate::search::{rank_font, SearchMatch};
+use crate::{Filter, Font, InstalledFonts, Location};
+
+/// Every known font, keyed by family, along with the repositories they were
+/// read from. Iteration is always sorted by family name.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Catalog {
+    fonts: HashMap<String, Font>,
+    repos: Vec<String>,
+}
+
+impl Catalog {
+    pub fn new(fonts: HashMap<String, Font>, mut repos: Vec<String>) -> Self {
+        repos.sort();
+        repos.dedup();
+        Catalog { fonts, repos }
+    }
+
+    /// Names of the repositories the catalog was built from, sorted.
+    pub fn repos(&self) -> &[String] {
+        &self.repos
+    }
+
+    pub fn fonts(&self) -> &HashMap<String, Font> {
+        &self.fonts
+    }
+
+    pub fn into_fonts(self) -> HashMap<String, Font> {
+        self.fonts
+    }
+
+    pub fn len(&self) -> usize {
+        self.fonts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty()
+    }
+
+    pub fn get(&self, family: &str) -> Option<&Font> {
+        self.fonts.get(family)
+    }
+
+    pub fn get_mut(&mut self, family: &str) -> Option<&mut Font> {
+        self.fonts.get_mut(family)
+    }
+
+    /// Looks `family` up ignoring case, preferring an exact match.
+    pub fn get_case_insensitive(&self, family: &str) -> Option<&Font> {
+        self.get(family).or_else(|| {
+            let family = family.to_lowercase();
+            self.iter().find(|font| font.family.to_lowercase() == family)
+        })
+    }
+
+    /// Family names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.fonts.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Every font, sorted by family name.
+    pub fn iter(&self) -> impl Iterator<Item = &Font> + '_ {
+        let mut fonts: Vec<&Font> = self.fonts.values().collect();
+        fonts.sort_by(|a, b| a.family.cmp(&b.family));
+        fonts.into_iter()
+    }
+
+    /// Fonts matching `query`, best matches first (see `rank_font`).
+    pub fn search(&self, query: &str) -> Vec<&Font> {
+        self.search_ranked(query)
+            .into_iter()
+            .map(|(font, _search_match)| font)
+            .collect()
+    }
+
+    /// Like `search`, along with how each font matched. Results are sorted by
+    /// the kind of match, then its score and then the family name.
+    pub fn search_ranked(&self, query: &str) -> Vec<(&Font, SearchMatch)> {
+        let mut results: Vec<(&Font, SearchMatch)> = self
+            .iter()
+            .filter_map(|font| rank_font(font, query).map(|search_match| (font, search_match)))
+            .collect();
+        // The sort is stable, so equal matches stay sorted by family
+        results.sort_by_key(|(_font, search_match)| (search_match.kind, search_match.score));
+        results
+    }
+
+    /// Fonts with files in `location`, whoever installed them.
+    pub fn installed(&self, location: &Location) -> Vec<&Font> {
+        self.iter()
+            .filter(|font| font.local_font.contains_key(location))
+            .collect()
+    }
+
+    /// Fonts font-catcher installed in `location` according to `installed_fonts`.
+    pub fn managed(&self, location: &Location, installed_fonts: &InstalledFonts) -> Vec<&Font> {
+        self.iter()
+            .filter(|font| installed_fonts.is_installed(&font.family, location))
+            .collect()
+    }
+
+    /// Fonts offered by the repository called `repo`.
+    pub fn in_repo(&self, repo: &str) -> Vec<&Font> {
+        self.iter().filter(|font| font.is_font_in_repo(repo)).collect()
+    }
+
+    /// Fonts meeting every condition of `filter`, with install state and
+    /// updates checked in `location`.
+    pub fn filter(
+        &mut self,
+        filter: &Filter,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+    ) -> Vec<&Font> {
+        let mut families: Vec<String> = self
+            .fonts
+            .iter_mut()
+            .filter_map(|(family, font)| {
+                filter
+                    .matches(font, location, installed_fonts)
+                    .then(|| family.clone())
+            })
+            .collect();
+        families.sort();
+        let fonts = &self.fonts;
+        families.iter().map(|family| &fonts[family]).collect()
+    }
+
+    /// Fonts installed in `location` with a newer version in some repository,
+    /// along with those repositories (see `Font::get_all_repos_with_update`).
+    pub fn with_updates(
+        &mut self,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+    ) -> Vec<(&Font, Vec<String>)> {
+        let mut updates: Vec<(String, Vec<String>)> = self
+            .fonts
+            .iter_mut()
+            .filter_map(|(family, font)| {
+                font.get_all_repos_with_update(location, installed_fonts)
+                    .map(|repos| (family.clone(), repos))
+            })
+            .collect();
+        updates.sort();
+        let fonts = &self.fonts;
+        updates
+            .into_iter()
+            .map(|(family, repos)| (&fonts[&family], repos))
+            .collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a Catalog {
+    type Item = &'a Font;
+    type IntoIter = std::vec::IntoIter<&'a Font>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter().collect::<Vec<&Font>>().into_iter()
+    }
+}
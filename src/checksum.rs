@@ -0,0 +1,82 @@
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{Error, Result};
+
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Checks `data` against a catalog hash written as `sha256:<hex>`,
+/// `sha512:<hex>` or a bare sha256 hex digest.
+pub(crate) fn verify(url: &str, data: &[u8], expected: &str) -> Result<()> {
+    let (algorithm, expected_digest) = match expected.split_once(':') {
+        Some((algorithm, digest)) => (algorithm.to_lowercase(), digest),
+        None => ("sha256".to_string(), expected),
+    };
+    let actual_digest = match algorithm.as_str() {
+        "sha256" => sha256_hex(data),
+        "sha512" => to_hex(&Sha512::digest(data)),
+        _ => {
+            return Err(Error::Parse(format!(
+                "unsupported hash algorithm \"{}\" for {}",
+                algorithm, url
+            )))
+        }
+    };
+    if actual_digest.eq_ignore_ascii_case(expected_digest.trim()) {
+        Ok(())
+    } else {
+        Err(Error::ChecksumMismatch {
+            url: url.to_string(),
+            expected: format!("{}:{}", algorithm, expected_digest.trim().to_lowercase()),
+            actual: format!("{}:{}", algorithm, actual_digest),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_mismatch(result: Result<()>) -> bool {
+        matches!(result, Err(Error::ChecksumMismatch { .. }))
+    }
+
+    #[test]
+    fn verify_accepts_matching_digests() {
+        let sha256 = sha256_hex(b"font");
+        let sha512 = to_hex(&Sha512::digest(b"font"));
+        assert!(verify("url", b"font", &format!("sha256:{}", sha256)).is_ok());
+        assert!(verify("url", b"font", &format!("SHA512:{}", sha512.to_uppercase())).is_ok());
+        assert!(verify("url", b"font", &sha256).is_ok());
+    }
+
+    #[test]
+    fn verify_reports_mismatches() {
+        let sha256 = sha256_hex(b"font");
+        let sha512 = to_hex(&Sha512::digest(b"font"));
+        assert!(is_mismatch(verify(
+            "url",
+            b"other",
+            &format!("sha256:{}", sha256)
+        )));
+        assert!(is_mismatch(verify(
+            "url",
+            b"other",
+            &format!("sha512:{}", sha512)
+        )));
+        assert!(is_mismatch(verify("url", b"other", &sha256)));
+    }
+
+    #[test]
+    fn verify_rejects_unknown_algorithms() {
+        assert!(matches!(
+            verify("url", b"font", "md5:abc"),
+            Err(Error::Parse(_))
+        ));
+    }
+}
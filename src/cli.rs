@@ -0,0 +1,316 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+
+use font_catcher as lib;
+
+use crate::output::Format;
+
+/// Family names of every font in the cached catalogs, for completions.
+fn font_candidates() -> Vec<CompletionCandidate> {
+    let repos = lib::repo_cache_dir()
+        .and_then(|dir| lib::read_repo_caches(&dir).ok())
+        .unwrap_or_default()
+        .repos;
+    let mut families: Vec<String> = repos
+        .values()
+        .flatten()
+        .filter_map(|repo_font| repo_font.family.clone())
+        .collect();
+    families.sort();
+    families.dedup();
+    families.into_iter().map(CompletionCandidate::new).collect()
+}
+
+/// Names of the cached catalogs, for completions.
+fn repo_candidates() -> Vec<CompletionCandidate> {
+    let repos = lib::repo_cache_dir()
+        .and_then(|dir| lib::read_repo_caches(&dir).ok())
+        .unwrap_or_default()
+        .repos;
+    let mut names: Vec<String> = repos.into_keys().collect();
+    names.sort();
+    names.into_iter().map(CompletionCandidate::new).collect()
+}
+
+/// A command-line utility for user and system font management
+#[derive(Parser, Debug)]
+#[command(name = "font-catcher", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Fetch the catalogs of the default repositories instead of reading the
+    /// cached ones
+    #[arg(long, global = true)]
+    pub use_preinstalled_repos: bool,
+
+    /// Print JSON records, same as `--format json`
+    #[arg(long, global = true, conflicts_with = "format")]
+    pub json: bool,
+
+    /// Output format
+    #[arg(long, global = true, value_enum, value_name = "FORMAT")]
+    pub format: Option<Format>,
+
+    /// Number of catalogs or font files fetched at the same time
+    #[arg(long, global = true, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Install and download exactly the files recorded in the lockfile,
+    /// failing if a font isn't locked or a file changed
+    #[arg(long, global = true)]
+    pub locked: bool,
+
+    /// Path of the lockfile, fonts.lock next to the project's fonts.toml or in
+    /// the data folder by default
+    #[arg(long, global = true, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    pub lockfile: Option<PathBuf>,
+}
+
+impl Cli {
+    pub fn format(&self) -> Format {
+        match self.json {
+            true => Format::Json,
+            false => self.format.unwrap_or(Format::Text),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the version and license
+    Version,
+    /// Download the latest catalogs of the repositories in repos.conf
+    UpdateRepos,
+    /// List the cached repository catalogs
+    ListLocalRepos,
+    /// Install fonts
+    Install {
+        /// Print the files that would be downloaded and written, and the
+        /// leftovers of a previous install that would be removed, without
+        /// installing anything
+        #[arg(long)]
+        dry_run: bool,
+        #[command(flatten)]
+        location: LocationArgs,
+        /// Install from this repository
+        #[arg(long, add = ArgValueCandidates::new(repo_candidates))]
+        repo: Option<String>,
+        /// Font families, e.g. "DejaVu Sans"
+        #[arg(required = true, value_name = "FONT", add = ArgValueCandidates::new(font_candidates))]
+        fonts: Vec<String>,
+    },
+    /// Download font files to a folder without installing them
+    Download {
+        /// Print the files that would be downloaded and where they would be
+        /// written, without downloading anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Download from this repository
+        #[arg(long, add = ArgValueCandidates::new(repo_candidates))]
+        repo: Option<String>,
+        /// Folder to write the files to
+        #[arg(long, default_value = ".", value_hint = clap::ValueHint::DirPath)]
+        path: PathBuf,
+        /// Font families, e.g. "DejaVu Sans"
+        #[arg(required = true, value_name = "FONT", add = ArgValueCandidates::new(font_candidates))]
+        fonts: Vec<String>,
+    },
+    /// Search the catalogs by name, creator, commentary and subsets
+    Search {
+        #[command(flatten)]
+        location: LocationArgs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Names, or parts of names, to look for
+        #[arg(required = true, value_name = "QUERY")]
+        queries: Vec<String>,
+    },
+    /// Remove installed fonts
+    Remove {
+        /// Print the files that would be deleted without deleting them
+        #[arg(long)]
+        dry_run: bool,
+        #[command(flatten)]
+        location: LocationArgs,
+        /// Also remove fonts that weren't installed by font-catcher
+        #[arg(long)]
+        force: bool,
+        /// Font families, e.g. "DejaVu Sans"
+        #[arg(required = true, value_name = "FONT", add = ArgValueCandidates::new(font_candidates))]
+        fonts: Vec<String>,
+    },
+    /// Show installed fonts with a newer version in some repository
+    CheckForUpdates {
+        #[command(flatten)]
+        location: LocationArgs,
+    },
+    /// Update every font installed by font-catcher
+    UpdateAll {
+        /// Print the fonts that would be updated and the files each would
+        /// download, write and remove, without updating anything
+        #[arg(long)]
+        dry_run: bool,
+        #[command(flatten)]
+        location: LocationArgs,
+        /// Also update fonts that weren't installed by font-catcher
+        #[arg(long)]
+        force: bool,
+    },
+    /// Update fonts to the newest version available
+    Update {
+        /// Print the files each font would download, write and remove, without
+        /// updating anything
+        #[arg(long)]
+        dry_run: bool,
+        #[command(flatten)]
+        location: LocationArgs,
+        /// Also update fonts that weren't installed by font-catcher
+        #[arg(long)]
+        force: bool,
+        /// Font families, e.g. "DejaVu Sans"
+        #[arg(required = true, value_name = "FONT", add = ArgValueCandidates::new(font_candidates))]
+        fonts: Vec<String>,
+    },
+    /// Show everything known about fonts
+    Info {
+        /// Font families, e.g. "DejaVu Sans"
+        #[arg(required = true, value_name = "FONT", add = ArgValueCandidates::new(font_candidates))]
+        fonts: Vec<String>,
+    },
+    /// List the fonts of every catalog, or the installed ones with --user or
+    /// --system
+    List {
+        #[command(flatten)]
+        location: LocationArgs,
+        /// With --user or --system, also list fonts that weren't installed by
+        /// font-catcher
+        #[arg(long)]
+        force: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Install the fonts listed in the project's fonts.toml that are missing
+    Sync {
+        #[command(flatten)]
+        project: ProjectArgs,
+        /// Print the files each missing font would download and write, without
+        /// installing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check that the fonts listed in the project's fonts.toml are installed,
+    /// exiting with an error if they aren't
+    Check {
+        #[command(flatten)]
+        project: ProjectArgs,
+    },
+    /// Print a script enabling shell completions
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page
+    Man,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct LocationArgs {
+    /// Use the fonts of the current user (the default)
+    #[arg(long, conflicts_with = "system")]
+    pub user: bool,
+    /// Use the fonts of every user, needs root/administrator privileges
+    #[arg(long)]
+    pub system: bool,
+}
+
+impl LocationArgs {
+    /// The location given on the command line, if any.
+    pub fn get(&self) -> Option<lib::Location> {
+        match (self.user, self.system) {
+            (_, true) => Some(lib::Location::System),
+            (true, _) => Some(lib::Location::User),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ProjectArgs {
+    /// Path of fonts.toml, looked up in the current folder and its parents
+    /// by default
+    #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    pub manifest: Option<PathBuf>,
+}
+
+impl ProjectArgs {
+    pub fn load(&self) -> lib::Result<lib::Project> {
+        let path = match &self.manifest {
+            Some(path) => path.clone(),
+            None => lib::Project::find(&std::env::current_dir()?)
+                .ok_or_else(|| lib::Error::NotFound(lib::PROJECT_FILE.to_string()))?,
+        };
+        lib::Project::load(&path)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct FilterArgs {
+    /// Only fonts offered by this repository
+    #[arg(long, add = ArgValueCandidates::new(repo_candidates))]
+    pub repo: Option<String>,
+    /// Only fonts offering this subset, e.g. cyrillic
+    #[arg(long)]
+    pub subset: Option<String>,
+    /// Only fonts offering this variant, e.g. 700italic
+    #[arg(long, value_name = "VARIANT")]
+    pub has_variant: Option<String>,
+    /// Only fonts of this category, e.g. monospace
+    #[arg(long)]
+    pub category: Option<String>,
+    /// Only fonts installed in the location
+    #[arg(long, conflicts_with = "not_installed")]
+    pub installed: bool,
+    /// Only fonts not installed in the location
+    #[arg(long)]
+    pub not_installed: bool,
+    /// Only installed fonts with a newer version in some repository
+    #[arg(long)]
+    pub updatable: bool,
+}
+
+impl FilterArgs {
+    pub fn filter(&self) -> lib::Filter {
+        lib::Filter {
+            subset: self.subset.clone(),
+            variant: self.has_variant.clone(),
+            category: self.category.clone(),
+            repo: self.repo.clone(),
+            installed: match (self.installed, self.not_installed) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            updatable: self.updatable,
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    Network(curl::Error),
+    Http { url: String, status: u32 },
+    Parse(String),
+    Io(io::Error),
+    FontLoad(String),
+    NotFound(String),
+    PermissionDenied(PathBuf),
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    InvalidSignature(String),
+    NotManaged(String),
+    Locked(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network(e) => write!(f, "network error: {}", e),
+            Error::Http { url, status } => write!(f, "{} returned HTTP status {}", url, status),
+            Error::Parse(e) => write!(f, "parse error: {}", e),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::FontLoad(e) => write!(f, "could not load font: {}", e),
+            Error::NotFound(what) => write!(f, "{} not found", what),
+            Error::PermissionDenied(path) => write!(
+                f,
+                "permission denied while writing to {}, try running as root/administrator",
+                path.display()
+            ),
+            Error::ChecksumMismatch {
+                url,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "checksum mismatch for {}: expected {}, got {}",
+                url, expected, actual
+            ),
+            Error::InvalidSignature(e) => write!(f, "signature verification failed: {}", e),
+            Error::NotManaged(family) => write!(f, "{} was not installed by font-catcher", family),
+            Error::Locked(e) => write!(f, "fonts.lock: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<curl::Error> for Error {
+    fn from(error: curl::Error) -> Self {
+        Error::Network(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Parse(error.to_string())
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Error::Parse(error.to_string())
+    }
+}
+
+impl From<font_kit::error::SelectionError> for Error {
+    fn from(error: font_kit::error::SelectionError) -> Self {
+        Error::FontLoad(error.to_string())
+    }
+}
+
+impl From<font_kit::error::FontLoadingError> for Error {
+    fn from(error: font_kit::error::FontLoadingError) -> Self {
+        Error::FontLoad(error.to_string())
+    }
+}
+
+impl Error {
+    pub(crate) fn with_path(error: io::Error, path: PathBuf) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(path),
+            _ => Error::Io(error),
+        }
+    }
+}
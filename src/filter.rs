@@ -0,0 +1,71 @@
+use crate::{Font, InstalledFonts, Location};
+
+/// Conditions a font has to meet, used by `Catalog::filter`. Unset
+/// conditions match every font.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Filter {
+    pub subset: Option<String>,
+    pub variant: Option<String>,
+    pub category: Option<String>,
+    pub repo: Option<String>,
+    /// `Some(true)` keeps only fonts installed in the location given to
+    /// `matches`, `Some(false)` only the ones that aren't.
+    pub installed: Option<bool>,
+    /// Keeps only fonts with a newer version in some repository.
+    pub updatable: bool,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subset(mut self, subset: &str) -> Self {
+        self.subset = Some(subset.to_string());
+        self
+    }
+
+    pub fn variant(mut self, variant: &str) -> Self {
+        self.variant = Some(variant.to_string());
+        self
+    }
+
+    pub fn category(mut self, category: &str) -> Self {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    pub fn repo(mut self, repo: &str) -> Self {
+        self.repo = Some(repo.to_string());
+        self
+    }
+
+    pub fn installed(mut self, installed: bool) -> Self {
+        self.installed = Some(installed);
+        self
+    }
+
+    pub fn updatable(mut self) -> Self {
+        self.updatable = true;
+        self
+    }
+
+    /// Whether `font` meets every condition. Install state and updates are
+    /// checked in `location`.
+    pub fn matches(
+        &self,
+        font: &mut Font,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+    ) -> bool {
+        self.subset.as_ref().is_none_or(|subset| font.has_subset(subset))
+            && self.variant.as_ref().is_none_or(|variant| font.has_variant(variant))
+            && self.category.as_ref().is_none_or(|category| font.is_in_category(category))
+            && self.repo.as_ref().is_none_or(|repo| font.is_font_in_repo(repo))
+            && self
+                .installed
+                .is_none_or(|installed| font.is_font_x_installed(location) == installed)
+            && (!self.updatable
+                || font.get_all_repos_with_update(location, installed_fonts).is_some())
+    }
+}
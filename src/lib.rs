@@ -1,11 +1,52 @@
-use std::collections::HashMap;
-use std::fs::{self, create_dir_all, File};
-use std::io::{Result, Write};
-use std::path::PathBuf;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
 use std::str;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use dirs::home_dir;
+use std::time::{Duration, SystemTime};
+
+use dirs::{data_dir, home_dir};
+
+mod archive;
+mod backend;
+mod cache;
+mod catalog;
+mod checksum;
+mod error;
+mod filter;
+mod lock;
+mod manifest;
+mod network;
+mod parallel;
+mod plan;
+mod project;
+mod search;
+mod signature;
+mod transaction;
+mod version;
+
+use transaction::Transaction;
+pub use backend::{
+    generate_repo_font_list, generate_repo_font_list_if_changed_with_backend,
+    generate_repo_font_list_with_backend, get_backend, LocalBackend, RepositoryBackend,
+    RepositoryMetadata, WebfontsBackend,
+};
+pub use catalog::Catalog;
+pub use cache::{
+    read_repo_caches, refresh_repo_cache, refresh_repo_caches, repo_cache_dir, CacheMetadata,
+    RefreshStatus, RepoCaches,
+};
+pub use error::{Error, Result};
+pub use filter::Filter;
+pub use lock::{LockSettings, LockedFont, LockedVariant, Lockfile, LOCK_FILE};
+pub use manifest::{installed_fonts_path, InstallRecord, InstalledFile, InstalledFonts};
+pub use network::{network_config, set_network_config, HttpOptions, NetworkConfig, Validators};
+pub use parallel::{jobs, set_jobs};
+pub use plan::{Plan, PlannedDownload};
+pub use project::{Check, FontRequirement, Project, Status, PROJECT_FILE};
+pub use search::{levenshtein, rank_font, MatchKind, SearchMatch};
+pub use signature::{invalid_trusted_keys, signature_url, trusted_keys_dir, Verify};
+pub use version::{compare_versions, font_file_version, version_matches};
 
 #[cfg(unix)]
 use dirs::font_dir;
@@ -29,17 +70,60 @@ fn windows_user_folder_fonts() -> Option<PathBuf> {
 #[cfg(target_os = "windows")]
 use self::windows_user_folder_fonts as font_dir;
 
+#[cfg(all(unix, not(target_os = "macos")))]
+fn system_font_dir() -> Option<PathBuf> {
+    Some(PathBuf::from("/usr/local/share/fonts/font-catcher"))
+}
+
+#[cfg(target_os = "macos")]
+fn system_font_dir() -> Option<PathBuf> {
+    Some(PathBuf::from("/Library/Fonts"))
+}
+
+#[cfg(target_os = "windows")]
+fn system_font_dir() -> Option<PathBuf> {
+    std::env::var_os("windir").and_then(|w| {
+        if w.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(w).join("Fonts"))
+        }
+    })
+}
+
+/// A file written by `Font::fetch_files`.
+struct FetchedFile {
+    variant: String,
+    url: String,
+    /// Hash of the content downloaded from `url`, as `sha256:<hex>`.
+    hash: String,
+    path: PathBuf,
+}
+
+fn to_selection(variants: &[&str]) -> Vec<String> {
+    variants.iter().map(|variant| variant.to_string()).collect()
+}
+
+/// Folder fonts are installed to for `location`.
+fn install_dir(location: &Location) -> Result<PathBuf> {
+    match location {
+        Location::User => {
+            font_dir().ok_or_else(|| Error::NotFound("user font directory".to_string()))
+        }
+        Location::System => {
+            system_font_dir().ok_or_else(|| Error::NotFound("system font directory".to_string()))
+        }
+        Location::Memory => Err(Error::NotFound("font directory for memory".to_string())),
+    }
+}
+
 use font_kit::handle::Handle;
 use font_kit::source::SystemSource;
 
 use chrono::offset::Utc;
 use chrono::{DateTime, NaiveDate};
 
-use curl::easy::Easy;
-
 use serde::{Deserialize, Serialize};
-use serde_json;
-use toml;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct FontsList {
@@ -52,42 +136,73 @@ pub struct Repository {
     pub name: String,
     pub url: String,
     pub key: Option<String>,
+    #[serde(rename = "type", default)]
+    pub backend: Option<String>,
+    #[serde(default)]
+    pub verify: Verify,
+    /// Proxy, CA bundle, headers and user agent for this repository.
+    #[serde(flatten)]
+    pub http: HttpOptions,
+    /// Seconds a cached catalog is used before `init_cached` refreshes it.
+    #[serde(default)]
+    pub max_age: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Repositories {
+    #[serde(default)]
     pub repo: Vec<Repository>,
+    #[serde(default)]
+    pub network: NetworkConfig,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
 pub struct RepoFont {
-    kind: Option<String>,
-    family: Option<String>,
-    variants: Vec<String>,
-    subsets: Option<Vec<String>>,
-    version: Option<String>,
-    lastModified: Option<String>,
-    files: HashMap<String, String>,
-    commentary: Option<String>,
-    creator: Option<String>,
+    pub kind: Option<String>,
+    pub family: Option<String>,
+    pub variants: Vec<String>,
+    pub subsets: Option<Vec<String>>,
+    /// `serif`, `sans-serif`, `display`, `handwriting` or `monospace` in the
+    /// Google Fonts API.
+    pub category: Option<String>,
+    pub version: Option<String>,
+    #[serde(rename = "lastModified")]
+    pub last_modified: Option<String>,
+    pub files: HashMap<String, String>,
+    pub commentary: Option<String>,
+    pub creator: Option<String>,
+    /// Glob selecting the archive member of each variant whose file is a
+    /// .zip/.tar.gz archive. Members are matched by name when absent.
+    #[serde(rename = "archiveMembers")]
+    pub archive_members: Option<HashMap<String, String>>,
+    /// Hash of each entry in `files`, as `sha256:<hex>` or `sha512:<hex>`.
+    pub hashes: Option<HashMap<String, String>>,
+    /// Request settings of the repository the font comes from, used for its
+    /// downloads. Set by `apply_repository_options`, never part of a catalog.
+    #[serde(skip)]
+    pub http: HttpOptions,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct LocalFont {
     family: Option<String>,
+    location: Location,
     variants: Option<Vec<String>>,
     files: Option<HashMap<String, PathBuf>>,
-    lastModified: Option<SystemTime>,
+    last_modified: Option<SystemTime>,
     installed: Option<bool>
 }
 
-#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Debug, Clone)]
 pub enum Location {
     User,
     System,
     Memory,
 }
 
+/// Installed files keyed by variant, see `Font::get_local_variant_files`.
+pub type VariantFiles = BTreeMap<String, Vec<PathBuf>>;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Font {
     family: String,
@@ -95,35 +210,128 @@ pub struct Font {
     local_font: HashMap<Location, LocalFont>,
 }
 
-fn download(url: &str) -> Vec<u8> {
-    let mut handle = Easy::new();
-    let mut file: Vec<u8> = Vec::new();
+pub fn font_catcher_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("font-catcher"))
+}
 
-    handle.url(url).unwrap();
-    let _location = handle.follow_location(true);
+/// Writes `contents` to `path` through a `.part` file renamed into place, so
+/// readers never see a half written file.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|e| Error::with_path(e, parent.to_path_buf()))?;
+    }
+    let mut partial_file = path.as_os_str().to_owned();
+    partial_file.push(".part");
+    let partial_file = PathBuf::from(partial_file);
+    fs::write(&partial_file, contents).map_err(|e| Error::with_path(e, partial_file.clone()))?;
+    fs::rename(&partial_file, path).map_err(|e| Error::with_path(e, path.to_path_buf()))?;
+    Ok(())
+}
 
-    {
-        let mut transfer = handle.transfer();
-        transfer
-            .write_function(|data| {
-                file.extend_from_slice(data);
-                Ok(data.len())
-            })
-            .unwrap();
-        transfer.perform().unwrap();
+fn download(url: &str, options: &HttpOptions) -> Result<Vec<u8>> {
+    if backend::is_local_url(url) {
+        let path = backend::local_path_from_url(url);
+        return fs::read(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Error::NotFound(path.display().to_string()),
+            _ => Error::Io(e),
+        });
+    }
+
+    network::download(url, options)
+}
+
+/// Interrupted downloads not resumed for this long are deleted.
+const PARTIAL_DOWNLOAD_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+fn partial_downloads_dir() -> Option<PathBuf> {
+    font_catcher_dir().map(|dir| dir.join("downloads"))
+}
+
+/// Deletes the partial downloads in `dir` untouched for longer than
+/// `PARTIAL_DOWNLOAD_MAX_AGE`.
+fn prune_partial_downloads(dir: &Path) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let old = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > PARTIAL_DOWNLOAD_MAX_AGE);
+        if old {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Where an interrupted download of `url` into `output_file` is kept so a
+/// later attempt can resume it.
+fn partial_download_path(output_file: &Path, url: &str) -> PathBuf {
+    let file_name = output_file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match partial_downloads_dir() {
+        Some(dir) => dir
+            .join(checksum::sha256_hex(format!("{}\n{}", url, file_name).as_bytes()) + ".part"),
+        None => {
+            let mut partial_file = output_file.as_os_str().to_owned();
+            partial_file.push(".part");
+            PathBuf::from(partial_file)
+        }
     }
-    file
 }
 
-fn download_file(output_file: &PathBuf, url: &str) -> Result<()> {
-    create_dir_all(output_file.parent().unwrap())?;
-    println!(
-        "Downloading to {} from {}...",
-        output_file.as_os_str().to_str().unwrap(),
-        url
-    );
-    let mut file = File::create(output_file)?;
-    file.write_all(download(url).as_slice())?;
+fn download_file(
+    output_file: &Path,
+    url: &str,
+    hash: Option<&str>,
+    options: &HttpOptions,
+) -> Result<()> {
+    if let Some(parent) = output_file.parent() {
+        create_dir_all(parent).map_err(|e| Error::with_path(e, parent.to_path_buf()))?;
+    }
+    if backend::is_local_url(url) {
+        let data = download(url, options)?;
+        if let Some(hash) = hash {
+            checksum::verify(url, &data, hash)?;
+        }
+        fs::write(output_file, data).map_err(|e| Error::with_path(e, output_file.to_path_buf()))?;
+        return Ok(());
+    }
+
+    if let Some(dir) = partial_downloads_dir() {
+        prune_partial_downloads(&dir);
+    }
+    let partial_file = partial_download_path(output_file, url);
+    let validators_file = network::validators_path(&partial_file);
+    if let Some(parent) = partial_file.parent() {
+        create_dir_all(parent).map_err(|e| Error::with_path(e, parent.to_path_buf()))?;
+    }
+    if let Err(e) = network::download_to_file(url, &partial_file, options) {
+        // Only keep partial files that something can be resumed from
+        if fs::metadata(&partial_file).is_ok_and(|metadata| metadata.len() == 0) {
+            let _ = fs::remove_file(&partial_file);
+            let _ = fs::remove_file(&validators_file);
+        }
+        return Err(e);
+    }
+    let _ = fs::remove_file(&validators_file);
+    if let Some(hash) = hash {
+        if let Err(e) = checksum::verify(url, &fs::read(&partial_file)?, hash) {
+            let _ = fs::remove_file(&partial_file);
+            return Err(e);
+        }
+    }
+    // The downloads folder may be on another file system than the output
+    if fs::rename(&partial_file, output_file).is_err() {
+        fs::copy(&partial_file, output_file)
+            .map_err(|e| Error::with_path(e, output_file.to_path_buf()))?;
+        fs::remove_file(&partial_file)?;
+    }
     Ok(())
 }
 
@@ -134,103 +342,225 @@ pub fn get_default_repos() -> Vec<Repository> {
             name: "Google Fonts".to_string(),
             url: "https://www.googleapis.com/webfonts/v1/webfonts?key={API_KEY}".to_string(),
             key: {
-                const PASSWORD: &'static str = env!("GOOGLE_FONTS_KEY");
+                const PASSWORD: &str = env!("GOOGLE_FONTS_KEY");
                 Some(PASSWORD.to_string())
             },
+            backend: Some("webfonts".to_string()),
+            verify: Verify::Off,
+            http: HttpOptions::default(),
+            max_age: Some(24 * 60 * 60),
         },
         Repository {
             name: "Open Font Repository".to_string(),
             url: "https://raw.githubusercontent.com/GustavoPeredo/open-font-repository/main/fonts.json".to_string(),
             key: None,
+            backend: Some("webfonts".to_string()),
+            verify: Verify::Off,
+            http: HttpOptions::default(),
+            max_age: Some(24 * 60 * 60),
         }
     ]
 }
 
 pub fn generate_repos_from_str(repos_as_str: &str) -> Result<Vec<Repository>> {
-    let repositories: Repositories = match toml::from_str(&repos_as_str) {
-        Ok(r) => r,
-        Err(e) => {
-            eprintln!("error: {:#}", e);
-            println!("Skipping reading from local repositories");
-            Repositories {
-                repo: Vec::<Repository>::new(),
-            }
-        }
-    };
+    let repositories: Repositories = toml::from_str(repos_as_str)?;
     Ok(repositories.repo)
 }
 
-pub fn generate_repos_from_file(repos_path: &PathBuf) -> Result<Vec<Repository>> {
-    Ok(generate_repos_from_str(&fs::read_to_string(repos_path)?)?)
+pub fn generate_repos_from_file(repos_path: &Path) -> Result<Vec<Repository>> {
+    generate_repos_from_str(&fs::read_to_string(repos_path)?)
+}
+
+pub fn generate_network_config_from_str(repos_as_str: &str) -> Result<NetworkConfig> {
+    let repositories: Repositories = toml::from_str(repos_as_str)?;
+    Ok(repositories.network)
+}
+
+pub fn generate_network_config_from_file(repos_path: &Path) -> Result<NetworkConfig> {
+    generate_network_config_from_str(&fs::read_to_string(repos_path)?)
 }
 
 pub fn generate_repo_font_list_from_str(font_list_as_str: &str) -> Result<Vec<RepoFont>> {
     Ok(serde_json::from_str::<FontsList>(font_list_as_str)?.items)
 }
 
-pub fn generate_repo_font_list_from_file(repo_path: &PathBuf) -> Result<Vec<RepoFont>> {
-    Ok(generate_repo_font_list_from_str(&fs::read_to_string(
-        repo_path,
-    )?)?)
+pub fn generate_repo_font_list_from_file(repo_path: &Path) -> Result<Vec<RepoFont>> {
+    generate_repo_font_list_from_str(&fs::read_to_string(repo_path)?)
 }
 
 pub fn generate_repo_font_list_from_url(
     repo_url: &str,
     key: Option<String>,
 ) -> Result<Vec<RepoFont>> {
+    generate_repo_font_list_from_url_verified(repo_url, key, Verify::Off, Path::new(""))
+}
+
+/// Like `generate_repo_font_list_from_url`, but checks the catalog against the
+/// ed25519 signature published next to it (`<url>.sig`) using the public keys
+/// stored in `trusted_keys_dir`.
+pub fn generate_repo_font_list_from_url_verified(
+    repo_url: &str,
+    key: Option<String>,
+    verify: Verify,
+    trusted_keys_dir: &Path,
+) -> Result<Vec<RepoFont>> {
+    Ok(fetch_repo_font_list_if_changed(
+        repo_url,
+        key,
+        verify,
+        trusted_keys_dir,
+        &HttpOptions::default(),
+        &Validators::default(),
+    )?
+    .map(|(fonts, _validators)| fonts)
+    .unwrap_or_default())
+}
+
+pub(crate) fn fetch_repo_font_list_if_changed(
+    repo_url: &str,
+    key: Option<String>,
+    verify: Verify,
+    trusted_keys_dir: &Path,
+    options: &HttpOptions,
+    cached: &Validators,
+) -> Result<Option<(Vec<RepoFont>, Validators)>> {
     let repo_url = match key {
         Some(key) => repo_url.replace("{API_KEY}", &key),
         None => repo_url.to_string(),
     };
-    Ok(generate_repo_font_list_from_str(
-        match str::from_utf8(download(&repo_url).as_slice()) {
-            Ok(s) => s,
-            Err(_) => "",
-        },
-    )?)
+    let (font_list, validators) = if backend::is_local_url(&repo_url) {
+        (download(&repo_url, options)?, Validators::default())
+    } else {
+        match network::download_if_changed(&repo_url, options, cached)? {
+            Some(response) => response,
+            None => return Ok(None),
+        }
+    };
+    signature::verify_catalog(&font_list, &repo_url, verify, trusted_keys_dir, options)?;
+    let fonts = generate_repo_font_list_from_str(
+        str::from_utf8(font_list.as_slice()).map_err(|e| Error::Parse(e.to_string()))?,
+    )?;
+    Ok(Some((fonts, validators)))
 }
 
-pub fn init() -> Result<HashMap<String, Font>> {
+/// Makes the font downloads of each repository in `repo_fonts` use its
+/// proxy, CA bundle, headers and user agent. Fonts are matched to `repos` by
+/// repository name.
+pub fn apply_repository_options(
+    repo_fonts: &mut HashMap<String, Vec<RepoFont>>,
+    repos: &[Repository],
+) {
+    for repo in repos.iter() {
+        for font in repo_fonts.get_mut(&repo.name).into_iter().flatten() {
+            font.http = repo.http.clone();
+        }
+    }
+}
+
+/// Fetches the catalogs of `repos` concurrently (see `set_jobs`), returning
+/// one result per repository in the same order.
+pub fn fetch_repo_font_lists(repos: &[Repository]) -> Vec<Result<Vec<RepoFont>>> {
+    parallel::map(repos, generate_repo_font_list)
+}
+
+pub fn init() -> Result<Catalog> {
     let local_fonts = generate_local_fonts(None)?;
     let default_repos = get_default_repos();
-    let repo_fonts: HashMap<String, Vec<RepoFont>> = default_repos
+    let mut repo_fonts: HashMap<String, Vec<RepoFont>> = default_repos
         .iter()
-        .map(|repo| {
-            (
-                repo.name.clone(),
-                generate_repo_font_list_from_url(&repo.url, repo.key.clone()).unwrap(),
-            )
-        })
-        .collect::<HashMap<String, Vec<RepoFont>>>();
+        .zip(fetch_repo_font_lists(&default_repos))
+        .map(|(repo, font_list)| Ok((repo.name.clone(), font_list?)))
+        .collect::<Result<HashMap<String, Vec<RepoFont>>>>()?;
+    apply_repository_options(&mut repo_fonts, &default_repos);
     Ok(generate_fonts_list(repo_fonts, local_fonts))
 }
 
-pub fn generate_local_fonts(location: Option<Location>) -> Result<Vec<LocalFont>> {
-    let fonts = SystemSource::new().all_families().unwrap();
+/// Like `init`, but keeps the catalogs of `repos` in the cache folder (see
+/// `repo_cache_dir`) and only refreshes the ones older than their `max_age`.
+/// When a catalog can't be refreshed, the cached copy is used instead.
+pub fn init_cached(repos: &[Repository]) -> Result<Catalog> {
+    let dir = repo_cache_dir().ok_or_else(|| Error::NotFound("data directory".to_string()))?;
+    for (repo, result) in repos.iter().zip(refresh_repo_caches(repos, &dir, false)) {
+        if let Err(e) = result {
+            if !cache::catalog_path(&dir, &repo.name).exists() {
+                return Err(e);
+            }
+        }
+    }
+    let mut repo_fonts = read_repo_caches(&dir)?.repos;
+    repo_fonts.retain(|name, _| repos.iter().any(|repo| &repo.name == name));
+    apply_repository_options(&mut repo_fonts, repos);
+    Ok(generate_fonts_list(repo_fonts, generate_local_fonts(None)?))
+}
 
-    let results = fonts.iter().map(|font_family| {
-        LocalFont {
-            family: Some(font_family.to_string()),
-            variants: None,
-            files: None,
-            lastModified: None,
-            installed: None
+/// Where a font handle lives: files under the home or user font directory
+/// belong to the user, other files to the system and the rest to memory.
+pub fn get_handle_location(handle: &Handle) -> Location {
+    match handle {
+        Handle::Path { ref path, .. } => {
+            if home_dir().is_some_and(|home| path.starts_with(home))
+                || font_dir().is_some_and(|user_dir| path.starts_with(user_dir))
+            {
+                Location::User
+            } else {
+                Location::System
+            }
+        }
+        _ => Location::Memory,
+    }
+}
+
+/// Scans the fonts known to the system, returning one `LocalFont` per family
+/// and location it is installed in. With `Some(location)`, only the fonts in
+/// that location are returned.
+pub fn generate_local_fonts(location: Option<Location>) -> Result<Vec<LocalFont>> {
+    let source = SystemSource::new();
+    let families = source.all_families()?;
+
+    let mut results: Vec<LocalFont> = Vec::new();
+    for family in families.iter() {
+        let family_handle = match source.select_family_by_name(family) {
+            Ok(family_handle) => family_handle,
+            Err(_) => continue,
+        };
+        let mut locations: Vec<Location> = Vec::new();
+        for handle in family_handle.fonts() {
+            let handle_location = get_handle_location(handle);
+            if !locations.contains(&handle_location)
+                && location.as_ref().is_none_or(|location| location == &handle_location)
+            {
+                locations.push(handle_location);
+            }
+        }
+        for handle_location in locations {
+            results.push(LocalFont {
+                family: Some(family.to_string()),
+                location: handle_location,
+                variants: None,
+                files: None,
+                last_modified: None,
+                installed: Some(true)
+            });
         }
-    }).collect::<Vec<LocalFont>>();
+    }
     Ok(results)
 }
 
 pub fn generate_fonts_list(
     repos_font_lists: HashMap<String, Vec<RepoFont>>,
     local_fonts: Vec<LocalFont>,
-) -> HashMap<String, Font> {
+) -> Catalog {
     let mut result: HashMap<String, Font> = HashMap::new();
 
     for (repo_name, repo_fonts) in repos_font_lists.iter() {
         for repo_font in repo_fonts {
-            let current_font = result.entry(repo_font.family.clone().unwrap()).or_insert(
+            let family = match &repo_font.family {
+                Some(family) => family.clone(),
+                None => continue,
+            };
+            let current_font = result.entry(family.clone()).or_insert(
                 Font {
-                    family: repo_font.family.clone().unwrap(),
+                    family,
                     repo_font: HashMap::new(),
                     local_font: HashMap::new(),
                 }
@@ -242,95 +572,101 @@ pub fn generate_fonts_list(
     }
 
     for local_font in local_fonts {
-        let local_font_format = Font {
-            family: local_font.family.clone().unwrap(),
-            repo_font: HashMap::new(),
-            local_font: HashMap::from([
-                (Location::System, local_font.clone()),
-                (Location::User, local_font.clone()),
-                (Location::Memory, local_font.clone())
-            ]),
+        let family = match &local_font.family {
+            Some(family) => family.clone(),
+            None => continue,
         };
-        let current_font = result.entry(local_font.family.clone().unwrap()).or_insert(
-            local_font_format
+        let current_font = result.entry(family.clone()).or_insert(
+            Font {
+                family,
+                repo_font: HashMap::new(),
+                local_font: HashMap::new(),
+            }
+        );
+        current_font.local_font.insert(
+            local_font.location.clone(), local_font,
         );
-        for location in [Location::User, Location::System, Location::Memory].iter() {
-            current_font.local_font.insert(
-                    location.clone(), local_font.clone(),
-            );
-        }
     }
-    result
+    Catalog::new(result, repos_font_lists.into_keys().collect())
+}
+
+/// Builds a `LocalFont` for each location the handles are installed in.
+pub fn generate_local_fonts_from_handles(handles: &[Handle]) -> HashMap<Location, LocalFont> {
+    let mut grouped: HashMap<Location, Vec<Handle>> = HashMap::new();
+    for handle in handles.iter() {
+        grouped
+            .entry(get_handle_location(handle))
+            .or_default()
+            .push(handle.clone());
+    }
+    grouped
+        .into_values()
+        .map(|handles| generate_local_font_from_handles(&handles))
+        .collect()
 }
 
 pub fn generate_local_font_from_handles(handles: &[Handle]) -> (Location, LocalFont) {
     let mut family_name = "".to_string();
     let mut variants: Vec<String> = Vec::new();
     let mut files: HashMap<String, PathBuf> = HashMap::new();
-    let mut lastModified = None;
+    let mut last_modified = None;
 
     let mut location = Location::Memory;
     
     for handle in handles.iter() {
-        match handle.load() {
-            Ok(font_info) => {
-                family_name = font_info.family_name();
-
-                let variant = match font_info.postscript_name() {
-                    Some(postscript_name) => {
-                        let mut var = postscript_name.replace(&family_name, "")
-                            .replace(&family_name.replace(" ", ""), "")
-                            .replace("-", " ");
-                        if var.len() == 0 {
-                            var = "Regular".to_string();
-                        }
-                        while variants.contains(&var) {
-                            var = var + "-";
-                        }
-                        var
-                    },
-                    None => "Regular".to_string()
-                };
-
-                variants.push(variant.clone());
-
-                match handle {
-                    Handle::Path {ref path, font_index: _} => {
-                        lastModified = Some(
-                            match fs::metadata(&path) {
-                                Ok(metadata) => {
-                                    match metadata.modified() {
-                                        Ok(time) => time,
-                                        Err(_) => SystemTime::now()
-                                    }
-                                },
-                                Err(_) => SystemTime::now()
-                            }
-                        );
-                        location = if path.starts_with(home_dir().unwrap()) {
-                            Location::User
-                        } else {
-                            Location::System
-                        };
-
-                        files.insert(
-                            variant,
-                            path.to_path_buf()
-                        );
-                    },
-                    Memory => {
-                        lastModified = Some(SystemTime::now());
-                        location = Location::Memory;
+        if let Ok(font_info) = handle.load() {
+            family_name = font_info.family_name();
+
+            let variant = match font_info.postscript_name() {
+                Some(postscript_name) => {
+                    let mut var = postscript_name.replace(&family_name, "")
+                        .replace(&family_name.replace(' ', ""), "")
+                        .replace('-', " ");
+                    if var.is_empty() {
+                        var = "Regular".to_string();
                     }
+                    while variants.contains(&var) {
+                        var += "-";
+                    }
+                    var
+                },
+                None => "Regular".to_string()
+            };
+
+            variants.push(variant.clone());
+
+            match handle {
+                Handle::Path {ref path, font_index: _} => {
+                    last_modified = Some(
+                        match fs::metadata(path) {
+                            Ok(metadata) => {
+                                match metadata.modified() {
+                                    Ok(time) => time,
+                                    Err(_) => SystemTime::now()
+                                }
+                            },
+                            Err(_) => SystemTime::now()
+                        }
+                    );
+                    location = get_handle_location(handle);
+
+                    files.insert(
+                        variant,
+                        path.to_path_buf()
+                    );
+                },
+                _ => {
+                    last_modified = Some(SystemTime::now());
+                    location = Location::Memory;
                 }
-            },
-            Err(_) => {}
+            }
         }
     }
     (
-        location,
+        location.clone(),
         LocalFont {
             family: Some(family_name),
+            location,
             variants: {
                 if !variants.is_empty() {
                     Some(variants)
@@ -345,7 +681,7 @@ pub fn generate_local_font_from_handles(handles: &[Handle]) -> (Location, LocalF
                     None
                 }
             },
-            lastModified: lastModified,
+            last_modified,
             installed: Some(true)
         }
     )
@@ -366,16 +702,14 @@ pub fn generate_local_font_from_handles(handles: &[Handle]) -> (Location, LocalF
                             None => {
                                 match SystemSource::new().select_family_by_name(&self.family) {
                                     Ok(family_handle) => {
-                                        let new_local_font = generate_local_font_from_handles(
+                                        let new_local_fonts = generate_local_fonts_from_handles(
                                             family_handle.fonts()
                                         );
-                                        self.local_font.insert(
-                                            new_local_font.0.clone(), new_local_font.1
-                                        );
-                                        if &new_local_font.0 == location {
-                                            return self.$func_name(location);
-                                        }
-                                        $default_return
+                                        let value = new_local_fonts
+                                            .get(location)
+                                            .and_then(|font| font.$variable.clone());
+                                        self.local_font.extend(new_local_fonts);
+                                        value.unwrap_or_else(|| $default_return)
                                     },
                                     Err(_) => $default_return
                                 }
@@ -392,7 +726,7 @@ impl Font {
     create_fn!(is_font_x_installed, installed, false, bool);
     create_fn!(get_local_x_variants, variants, Vec::new(), Vec<String>);
     create_fn!(get_local_x_files, files, HashMap::new(), HashMap<String, PathBuf>);
-    create_fn!(get_local_x_last_modified, lastModified, SystemTime::now(), SystemTime);
+    create_fn!(get_local_x_last_modified, last_modified, SystemTime::now(), SystemTime);
     create_fn!(get_local_x_font_family, family, "".to_string(), String);
 
     pub fn is_font_system_installed(&mut self) -> bool {
@@ -457,15 +791,16 @@ impl Font {
         self.get_local_x_font_family(&Location::Memory).to_string()
     }
 
+    pub fn get_family(&self) -> String {
+        self.family.clone()
+    }
+
     pub fn is_font_in_repo(&self, repo: &str) -> bool {
-        match &self.repo_font.get(repo) {
-            Some(_repo_font) => true,
-            None => false,
-        }
+        self.repo_font.contains_key(repo)
     }
 
     pub fn get_repos_availability(&self) -> Option<Vec<String>> {
-        if self.repo_font.len() > 0 {
+        if !self.repo_font.is_empty() {
             Some(self.repo_font.keys().cloned().collect())
         } else {
             None
@@ -473,24 +808,18 @@ impl Font {
     }
 
     pub fn get_repo_variants(&self, repo: &str) -> Option<Vec<String>> {
-        match &self.repo_font.get(repo) {
-            Some(repo_font) => Some(repo_font.variants.clone()),
-            None => None,
-        }
+        self.repo_font.get(repo).map(|repo_font| repo_font.variants.clone())
     }
 
     pub fn get_repo_files(&self, repo: &str) -> Option<HashMap<String, String>> {
-        match &self.repo_font.get(repo) {
-            Some(repo_font) => Some(repo_font.files.clone()),
-            None => None,
-        }
+        self.repo_font.get(repo).map(|repo_font| repo_font.files.clone())
     }
 
     pub fn get_repo_last_modified(&self, repo: &str) -> Option<DateTime<Utc>> {
         match &self.repo_font.get(repo) {
-            Some(repo_font) => match &repo_font.lastModified {
+            Some(repo_font) => match &repo_font.last_modified {
                 Some(date) => {
-                    let naive_date = NaiveDate::parse_from_str(&date, "%Y-%m-%d");
+                    let naive_date = NaiveDate::parse_from_str(date, "%Y-%m-%d");
                     match naive_date {
                         Ok(naive_date) => {
                             Some(DateTime::from_utc(naive_date.and_hms(0, 0, 0), Utc))
@@ -508,253 +837,971 @@ impl Font {
     }
 
     pub fn get_repo_family(&self, repo: &str) -> Option<String> {
-        match &self.repo_font.get(repo) {
-            Some(repo_font) => Some(repo_font.family.clone().unwrap()),
-            None => None,
-        }
+        self.repo_font.get(repo).and_then(|repo_font| repo_font.family.clone())
     }
 
     pub fn get_repo_subsets(&self, repo: &str) -> Option<Vec<String>> {
-        match &self.repo_font.get(repo) {
-            Some(repo_font) => match &repo_font.subsets {
-                Some(i) => Some(i.clone()),
-                None => None,
-            },
-            None => None,
-        }
+        self.repo_font.get(repo).and_then(|repo_font| repo_font.subsets.clone())
+    }
+
+    pub fn get_repo_category(&self, repo: &str) -> Option<String> {
+        self.repo_font.get(repo).and_then(|repo_font| repo_font.category.clone())
+    }
+
+    /// Whether any repository offers the font with `subset`, ignoring case.
+    pub fn has_subset(&self, subset: &str) -> bool {
+        self.repo_font.values().any(|repo_font| {
+            repo_font
+                .subsets
+                .iter()
+                .flatten()
+                .any(|font_subset| font_subset.eq_ignore_ascii_case(subset))
+        })
+    }
+
+    /// Whether any repository offers `variant` of the font, e.g. `700italic`.
+    pub fn has_variant(&self, variant: &str) -> bool {
+        self.repo_font.values().any(|repo_font| {
+            repo_font
+                .variants
+                .iter()
+                .any(|font_variant| font_variant.eq_ignore_ascii_case(variant))
+        })
+    }
+
+    /// Whether any repository files the font under `category`, ignoring case.
+    pub fn is_in_category(&self, category: &str) -> bool {
+        self.repo_font.values().any(|repo_font| {
+            repo_font
+                .category
+                .as_ref()
+                .is_some_and(|font_category| font_category.eq_ignore_ascii_case(category))
+        })
     }
 
     pub fn get_repo_version(&self, repo: &str) -> Option<String> {
-        match &self.repo_font.get(repo) {
-            Some(repo_font) => match &repo_font.version {
-                Some(i) => Some(i.clone()),
-                None => None,
-            },
-            None => None,
-        }
+        self.repo_font.get(repo).and_then(|repo_font| repo_font.version.clone())
     }
 
     pub fn get_repo_commentary(&self, repo: &str) -> Option<String> {
-        match &self.repo_font.get(repo) {
-            Some(repo_font) => match &repo_font.commentary {
-                Some(i) => Some(i.clone()),
-                None => None,
-            },
-            None => None,
-        }
+        self.repo_font.get(repo).and_then(|repo_font| repo_font.commentary.clone())
     }
 
     pub fn get_repo_creator(&self, repo: &str) -> Option<String> {
-        match &self.repo_font.get(repo) {
-            Some(repo_font) => match &repo_font.creator {
-                Some(i) => Some(i.clone()),
-                None => None,
-            },
-            None => None,
+        self.repo_font.get(repo).and_then(|repo_font| repo_font.creator.clone())
+    }
+
+    /// Version of the font installed in `location`: the repository version
+    /// recorded when font-catcher installed it, otherwise the version stored
+    /// in the font files themselves.
+    pub fn get_local_version(
+        &mut self,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+    ) -> Option<String> {
+        if let Some(version) = installed_fonts
+            .get(&self.family, location)
+            .and_then(|record| record.version.clone())
+        {
+            return Some(version);
         }
+        let mut files: Vec<PathBuf> = self.get_local_x_files(location).into_values().collect();
+        files.sort();
+        files.iter().find_map(|file| font_file_version(file))
     }
 
-    pub fn get_all_repos_with_update_user(&mut self) -> Option<Vec<String>> {
-        let mut result: Vec<String> = Vec::new();
-        let local_last_modified = &self.get_local_user_last_modified();
-        match &self.get_repos_availability() {
-            Some(repos) => {
-                for repo in repos.iter() {
-                    match &self.get_repo_last_modified(repo) {
-                        Some(repo_last_modified) => {
-                            if repo_last_modified > local_last_modified {
-                                result.push(repo.to_string());
-                            }
-                        }
-                        None => {}
-                    }
-                }
+    /// Files installed in `location` keyed by variant, named like repository
+    /// variants (`regular`, `700italic`...). The names recorded in the
+    /// manifest are used for fonts font-catcher installed, otherwise they are
+    /// read from the font files. Several files can hold the same variant,
+    /// e.g. a condensed and a normal width one.
+    pub fn get_local_variant_files(
+        &mut self,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+    ) -> VariantFiles {
+        let mut variants = VariantFiles::new();
+        if let Some(record) = installed_fonts.get(&self.family, location) {
+            for file in record.files.iter() {
+                variants
+                    .entry(file.variant.clone())
+                    .or_default()
+                    .push(file.path.clone());
             }
-            None => {}
+            return variants;
         }
-        if result.len() > 0 {
-            Some(result)
-        } else {
-            None
+        let mut files: Vec<PathBuf> = self.get_local_x_files(location).into_values().collect();
+        files.sort();
+        for file in files {
+            let variant = backend::variant_of_file(&file)
+                .unwrap_or_else(|| file.display().to_string());
+            variants.entry(variant).or_default().push(file);
         }
+        variants
     }
-    pub fn get_all_repos_with_update_system(&mut self) -> Option<Vec<String>> {
+
+    /// Variants that differ between `repo` and the font installed in
+    /// `location`: the ones only the repository offers and the ones only
+    /// installed locally, both sorted.
+    pub fn get_variant_differences(
+        &mut self,
+        repo: &str,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+    ) -> (Vec<String>, Vec<String>) {
+        let repo_variants = self.get_repo_variants(repo).unwrap_or_default();
+        let local_variants: Vec<String> = self
+            .get_local_variant_files(location, installed_fonts)
+            .into_keys()
+            .collect();
+        let mut missing: Vec<String> = repo_variants
+            .iter()
+            .filter(|variant| !local_variants.contains(variant))
+            .cloned()
+            .collect();
+        let mut extra: Vec<String> = local_variants
+            .into_iter()
+            .filter(|variant| !repo_variants.contains(variant))
+            .collect();
+        missing.sort();
+        extra.sort();
+        (missing, extra)
+    }
+
+    fn is_repo_newer(
+        &self,
+        repo: &str,
+        installed_version: Option<&str>,
+        record: Option<&InstallRecord>,
+    ) -> bool {
+        let available_version = self.get_repo_version(repo);
+        let ordering = match (available_version.as_deref(), installed_version) {
+            (Some(available), Some(installed)) => compare_versions(available, installed),
+            _ => None,
+        };
+        if let Some(ordering) = ordering {
+            return ordering == Ordering::Greater;
+        }
+        // Versions that can't be compared, such as a font file's `Version 2.37`
+        // and a catalog's `v10`, can only be told apart when we installed them
+        let record = match record {
+            Some(record) => record,
+            None => return false,
+        };
+        let available_last_modified = self
+            .repo_font
+            .get(repo)
+            .and_then(|repo_font| repo_font.last_modified.clone());
+        match (available_last_modified, &record.last_modified) {
+            (Some(available), Some(installed)) => &available > installed,
+            _ => available_version.is_some() && available_version.as_deref() != installed_version,
+        }
+    }
+
+    /// Repositories offering a newer version of the font than the one
+    /// installed in `location`. Versions numbered the same way are compared
+    /// (see `compare_versions`), and the manifest record is used when
+    /// font-catcher installed the font.
+    pub fn get_all_repos_with_update(
+        &mut self,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+    ) -> Option<Vec<String>> {
+        let record = installed_fonts.get(&self.family, location);
+        if record.is_none() && !self.is_font_x_installed(location) {
+            return None;
+        }
+        let installed_version = self.get_local_version(location, installed_fonts);
+
         let mut result: Vec<String> = Vec::new();
-        let local_last_modified = &self.get_local_system_last_modified();
-        match &self.get_repos_availability() {
-            Some(repos) => {
-                for repo in repos.iter() {
-                    match &self.get_repo_last_modified(repo) {
-                        Some(repo_last_modified) => {
-                            if repo_last_modified > local_last_modified {
-                                result.push(repo.to_string());
-                            }
-                        }
-                        None => {}
-                    }
+        if let Some(repos) = &self.get_repos_availability() {
+            for repo in repos.iter() {
+                if self.is_repo_newer(repo, installed_version.as_deref(), record) {
+                    result.push(repo.to_string());
                 }
             }
-            None => {}
         }
-        if result.len() > 0 {
+        if !result.is_empty() {
+            result.sort();
             Some(result)
         } else {
             None
         }
     }
 
+    pub fn get_all_repos_with_update_user(&mut self) -> Option<Vec<String>> {
+        let installed_fonts = InstalledFonts::load().unwrap_or_default();
+        self.get_all_repos_with_update(&Location::User, &installed_fonts)
+    }
+    pub fn get_all_repos_with_update_system(&mut self) -> Option<Vec<String>> {
+        let installed_fonts = InstalledFonts::load().unwrap_or_default();
+        self.get_all_repos_with_update(&Location::System, &installed_fonts)
+    }
+
     pub fn is_update_available_user(&mut self) -> bool {
-        match self.get_all_repos_with_update_user() {
-            Some(_repos) => true,
-            None => false,
-        }
+        self.get_all_repos_with_update_user().is_some()
     }
     pub fn is_update_available_system(&mut self) -> bool {
-        match self.get_all_repos_with_update_system() {
-            Some(_repos) => true,
-            None => false,
-        }
+        self.get_all_repos_with_update_system().is_some()
     }
 
+    /// The first repository offering the font, by name, so the same one is
+    /// picked every time.
     pub fn get_first_available_repo(&self) -> Option<String> {
-        let repos = &self.get_repos_availability();
-        match repos {
-            Some(repos) => Some(repos.first().unwrap().to_string()),
-            None => None,
+        self.repo_font.keys().min().map(|repo| repo.to_string())
+    }
+
+    /// What `uninstall` would remove: the files font-catcher installed for
+    /// this font in `location`. With `force`, every file of the family found
+    /// there as well, even the ones font-catcher did not install.
+    pub fn plan_uninstall(
+        &mut self,
+        location: &Location,
+        force: bool,
+        installed_fonts: &InstalledFonts,
+    ) -> Result<Plan> {
+        let mut files: Vec<PathBuf> = match installed_fonts.get(&self.family, location) {
+            Some(record) => record.files.iter().map(|file| file.path.clone()).collect(),
+            None if force => Vec::new(),
+            None => return Err(Error::NotManaged(self.family.clone())),
+        };
+        if force {
+            let mut local_files: Vec<PathBuf> =
+                self.get_local_x_files(location).into_values().collect();
+            local_files.sort();
+            for file in local_files {
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+            }
         }
+        Ok(Plan {
+            family: self.family.clone(),
+            location: Some(location.clone()),
+            removals: files,
+            ..Plan::default()
+        })
     }
 
-    pub fn uninstall_from_user(&mut self, output: bool) -> Result<()> {
-        for (_name, file) in self.get_local_user_files(){
+    /// What `uninstall_variants` would remove: the files of `variants` in
+    /// `location`. Unless `force` is set, the font has to be installed by
+    /// font-catcher.
+    pub fn plan_uninstall_variants(
+        &mut self,
+        location: &Location,
+        variants: &[&str],
+        force: bool,
+        installed_fonts: &InstalledFonts,
+    ) -> Result<Plan> {
+        if !force && !installed_fonts.is_installed(&self.family, location) {
+            return Err(Error::NotManaged(self.family.clone()));
+        }
+        let files = self.get_local_variant_files(location, installed_fonts);
+        let mut removals = Vec::new();
+        for variant in variants {
+            let variant_files = files.get(*variant).ok_or_else(|| {
+                Error::NotFound(format!("installed variant {} of {}", variant, self.family))
+            })?;
+            for file in variant_files {
+                if !removals.contains(file) {
+                    removals.push(file.clone());
+                }
+            }
+        }
+        Ok(Plan {
+            family: self.family.clone(),
+            location: Some(location.clone()),
+            removals,
+            ..Plan::default()
+        })
+    }
+
+    fn remove_files(files: Vec<PathBuf>, output: bool) -> Result<Vec<PathBuf>> {
+        let mut removed = Vec::new();
+        for file in files {
             if output {
                 println!("Removing {}...", &file.display());
             }
-            fs::remove_file(&file)?;
+            match fs::remove_file(&file) {
+                Ok(()) => removed.push(file),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(Error::with_path(e, file)),
+            }
         }
-        self.local_font.insert(
-            Location::User,
-            LocalFont {
-                family: None,
-                variants: None,
-                files: None,
-                lastModified: None,
-                installed: Some(false)
+        Ok(removed)
+    }
+
+    /// Removes the font from `location` and returns the files removed. Only
+    /// fonts installed by font-catcher are removed unless `force` is set.
+    pub fn uninstall(
+        &mut self,
+        location: &Location,
+        force: bool,
+        output: bool,
+    ) -> Result<Vec<PathBuf>> {
+        let mut installed_fonts = InstalledFonts::load()?;
+        let plan = self.plan_uninstall(location, force, &installed_fonts)?;
+
+        let removed = Self::remove_files(plan.removals, output)?;
+        if installed_fonts.remove(&self.family, location).is_some() {
+            installed_fonts.save()?;
+        }
+
+        self.local_font.remove(location);
+        Ok(removed)
+    }
+
+    /// Removes only `variants` of the font from `location` and returns the
+    /// files removed. The other variants stay installed, and are the ones
+    /// later updates fetch.
+    pub fn uninstall_variants(
+        &mut self,
+        location: &Location,
+        variants: &[&str],
+        force: bool,
+        output: bool,
+    ) -> Result<Vec<PathBuf>> {
+        let mut installed_fonts = InstalledFonts::load()?;
+        let plan = self.plan_uninstall_variants(location, variants, force, &installed_fonts)?;
+
+        let removed = Self::remove_files(plan.removals.clone(), output)?;
+        if let Some(mut record) = installed_fonts.remove(&self.family, location) {
+            record.files.retain(|file| !plan.removals.contains(&file.path));
+            if !record.files.is_empty() {
+                let mut remaining: Vec<String> =
+                    record.files.iter().map(|file| file.variant.clone()).collect();
+                remaining.sort();
+                remaining.dedup();
+                record.variants = Some(remaining);
+                installed_fonts.record(record);
             }
-        );
-        Ok(())
+            installed_fonts.save()?;
+        }
+
+        let remaining: Vec<Handle> = self
+            .get_local_x_files(location)
+            .into_values()
+            .filter(|file| !plan.removals.contains(file))
+            .map(|file| Handle::from_path(file, 0))
+            .collect();
+        if remaining.is_empty() {
+            self.local_font.remove(location);
+        } else {
+            let local_font = generate_local_font_from_handles(&remaining);
+            self.local_font.insert(location.clone(), local_font.1);
+        }
+        Ok(removed)
     }
 
-    pub fn uninstall_from_system(&mut self, output: bool) -> Result<()> {
-        for (_name, file) in self.get_local_system_files() {
-            if output {
-                println!("Removing {}...", &file.display());
+    pub fn uninstall_from_user(&mut self, output: bool) -> Result<Vec<PathBuf>> {
+        self.uninstall(&Location::User, false, output)
+    }
+
+    pub fn uninstall_from_system(&mut self, output: bool) -> Result<Vec<PathBuf>> {
+        self.uninstall(&Location::System, false, output)
+    }
+
+    fn resolve_repo(&self, repo: Option<&str>) -> Result<String> {
+        match repo {
+            Some(repo) if self.is_font_in_repo(repo) => Ok(repo.to_string()),
+            Some(repo) => Err(Error::NotFound(format!("{} in {}", self.family, repo))),
+            None => self
+                .get_first_available_repo()
+                .ok_or_else(|| Error::NotFound(format!("{} in any repository", self.family))),
+        }
+    }
+
+    fn output_file_name(&self, repo: &str, variant: &str, extension: &str) -> String {
+        format!(
+            "{}-{}.{}",
+            archive::file_name_part(
+                &self.get_repo_family(repo).unwrap_or_else(|| self.family.clone())
+            ),
+            archive::file_name_part(variant),
+            extension
+        )
+    }
+
+    pub fn get_repo_hashes(&self, repo: &str) -> Option<HashMap<String, String>> {
+        self.repo_font.get(repo).and_then(|repo_font| repo_font.hashes.clone())
+    }
+
+    fn get_repo_archive_members(&self, repo: &str) -> HashMap<String, String> {
+        self.repo_font
+            .get(repo)
+            .and_then(|repo_font| repo_font.archive_members.clone())
+            .unwrap_or_default()
+    }
+
+    /// Downloads every variant of the font from `repo` into `download_path`
+    /// and returns the paths of the written files. Variants shipped inside
+    /// .zip/.tar.gz archives are extracted and only the matching font files
+    /// are kept. Files are staged first and only moved into `download_path`
+    /// once all of them downloaded and load as fonts, so a failure leaves the
+    /// directory untouched. Up to `jobs()` files are downloaded at once.
+    /// With `lock`, what is fetched is recorded in its lockfile, or in locked
+    /// mode the files recorded there are fetched instead.
+    pub fn download(
+        &self,
+        repo: Option<&str>,
+        download_path: &Path,
+        lock: Option<&LockSettings>,
+        output: bool,
+    ) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .fetch_variants(repo, None, download_path, lock, output, |_fetched| Ok(()))?
+            .into_iter()
+            .map(|(_variant, path)| path)
+            .collect())
+    }
+
+    /// Like `download`, fetching only `variants`.
+    pub fn download_variants(
+        &self,
+        repo: Option<&str>,
+        variants: &[&str],
+        download_path: &Path,
+        lock: Option<&LockSettings>,
+        output: bool,
+    ) -> Result<Vec<PathBuf>> {
+        let selection = to_selection(variants);
+        Ok(self
+            .fetch_variants(repo, Some(&selection), download_path, lock, output, |_fetched| {
+                Ok(())
+            })?
+            .into_iter()
+            .map(|(_variant, path)| path)
+            .collect())
+    }
+
+    /// Files of `repo` to fetch, sorted by variant: the ones of `selection`,
+    /// or every file without one.
+    fn selected_files(
+        &self,
+        repo: &str,
+        selection: Option<&[String]>,
+    ) -> Result<Vec<(String, String)>> {
+        let mut files: Vec<(String, String)> =
+            self.get_repo_files(repo).unwrap_or_default().into_iter().collect();
+        if let Some(selection) = selection {
+            if let Some(variant) = selection
+                .iter()
+                .find(|variant| !files.iter().any(|(offered, _file)| offered == *variant))
+            {
+                return Err(Error::NotFound(format!("{} {} in {}", self.family, variant, repo)));
             }
-            fs::remove_file(&file)?;
+            files.retain(|(variant, _file)| selection.contains(variant));
         }
-        self.local_font.insert(
-            Location::System,
-            LocalFont {
-                family: None,
-                variants: None,
-                files: None,
-                lastModified: None,
-                installed: Some(false)
+        files.sort();
+        Ok(files)
+    }
+
+    /// Repository fetches use: the one recorded in the lockfile of `lock` in
+    /// locked mode, otherwise the one `resolve_repo` picks.
+    fn fetch_repo(&self, repo: Option<&str>, lock: Option<&LockSettings>) -> Result<String> {
+        match lock {
+            Some(settings) if settings.locked => {
+                let lockfile = Lockfile::load(&settings.path)?;
+                Ok(self.locked_copy(repo, None, &lockfile)?.1)
+            }
+            _ => self.resolve_repo(repo),
+        }
+    }
+
+    /// The font and repository to fetch from: in locked mode a copy of the
+    /// font holding exactly what the lockfile of `lock` records.
+    fn fetch_source(
+        &self,
+        repo: Option<&str>,
+        selection: Option<&[String]>,
+        lock: Option<&LockSettings>,
+    ) -> Result<(Font, String)> {
+        match lock {
+            Some(settings) if settings.locked => {
+                let lockfile = Lockfile::load(&settings.path)?;
+                self.locked_copy(repo, selection, &lockfile)
             }
+            _ => Ok((self.clone(), self.resolve_repo(repo)?)),
+        }
+    }
+
+    /// A copy of the font whose locked repository offers only the files,
+    /// hashes and version recorded in `lockfile`.
+    fn locked_copy(
+        &self,
+        repo: Option<&str>,
+        selection: Option<&[String]>,
+        lockfile: &Lockfile,
+    ) -> Result<(Font, String)> {
+        let locked = lockfile
+            .get(&self.family)
+            .ok_or_else(|| Error::Locked(format!("{} isn't locked", self.family)))?;
+        if let Some(repo) = repo.filter(|repo| *repo != locked.repo) {
+            return Err(Error::Locked(format!(
+                "{} is locked to {}, not {}",
+                self.family, locked.repo, repo
+            )));
+        }
+        for variant in selection.unwrap_or_default() {
+            if !locked.variants.iter().any(|locked| &locked.variant == variant) {
+                return Err(Error::Locked(format!("{} {} isn't locked", self.family, variant)));
+            }
+        }
+
+        let mut repo_font = self.repo_font.get(&locked.repo).cloned().unwrap_or(RepoFont {
+            family: Some(self.family.clone()),
+            ..RepoFont::default()
+        });
+        repo_font.variants = locked.variants.iter().map(|locked| locked.variant.clone()).collect();
+        repo_font.version = locked.version.clone();
+        repo_font.last_modified = locked.last_modified.clone();
+        repo_font.files = locked
+            .variants
+            .iter()
+            .map(|locked| (locked.variant.clone(), locked.url.clone()))
+            .collect();
+        repo_font.hashes = Some(
+            locked
+                .variants
+                .iter()
+                .map(|locked| (locked.variant.clone(), locked.hash.clone()))
+                .collect(),
+        );
+        repo_font.archive_members = Some(
+            locked
+                .variants
+                .iter()
+                .filter_map(|locked| {
+                    let member = locked.archive_member.clone()?;
+                    Some((locked.variant.clone(), member))
+                })
+                .collect(),
         );
-        Ok(())
+
+        let mut font = self.clone();
+        font.repo_font.insert(locked.repo.clone(), repo_font);
+        Ok((font, locked.repo.clone()))
     }
 
-    pub fn download(
+    /// Downloads the variants of `selection` (every variant without one) into
+    /// `download_path`. In locked mode the files recorded in the lockfile of
+    /// `lock` are fetched instead, otherwise what was fetched is recorded there.
+    /// `finish` runs once the files are in place, see `Transaction::commit`.
+    pub(crate) fn fetch_variants(
         &self,
         repo: Option<&str>,
-        download_path: &PathBuf,
+        selection: Option<&[String]>,
+        download_path: &Path,
+        lock: Option<&LockSettings>,
         output: bool,
-    ) -> Result<()> {
-        let repos = self.get_first_available_repo();
-        let repo = match repo {
-            Some(repo) => repo,
-            None => match &repos {
-                Some(repo) => repo,
-                None => "",
-            },
-        };
-        match self.get_repo_files(repo) {
-            Some(files) => {
-                for (variant, file) in files {
-                    let extension: &str = file.split(".").collect::<Vec<&str>>().last().unwrap();
-
-                    if output {
-                        println!(
-                            "Downloading {} from {}",
-                            &format!(
-                                "{}-{}.{}",
-                                &self.get_repo_family(repo).unwrap(),
-                                &variant,
-                                &extension
-                            ),
-                            &file
-                        );
-                    }
-                    download_file(
-                        &download_path.join(&format!(
-                            "{}-{}.{}",
-                            &self.get_repo_family(repo).unwrap(),
-                            &variant,
-                            &extension
-                        )),
-                        &file,
-                    )?;
+        finish: impl FnOnce(&[FetchedFile]) -> Result<()>,
+    ) -> Result<Vec<(String, PathBuf)>> {
+        let (font, repo) = self.fetch_source(repo, selection, lock)?;
+        let fetched = font.fetch_files(&repo, selection, download_path, output, finish)?;
+        if let Some(settings) = lock.filter(|settings| !settings.locked) {
+            let archive_members = font.get_repo_archive_members(&repo);
+            let mut lockfile = Lockfile::load(&settings.path)?;
+            lockfile.record(LockedFont {
+                family: font.family.clone(),
+                repo: repo.clone(),
+                version: font.get_repo_version(&repo),
+                last_modified: font
+                    .repo_font
+                    .get(&repo)
+                    .and_then(|repo_font| repo_font.last_modified.clone()),
+                variants: fetched
+                    .iter()
+                    .map(|file| LockedVariant {
+                        variant: file.variant.clone(),
+                        url: file.url.clone(),
+                        hash: file.hash.clone(),
+                        archive_member: archive_members.get(&file.variant).cloned(),
+                    })
+                    .collect(),
+            });
+            lockfile.save(&settings.path)?;
+        }
+        Ok(fetched
+            .into_iter()
+            .map(|file| (file.variant, file.path))
+            .collect())
+    }
+
+    fn fetch_files(
+        &self,
+        repo: &str,
+        selection: Option<&[String]>,
+        download_path: &Path,
+        output: bool,
+        finish: impl FnOnce(&[FetchedFile]) -> Result<()>,
+    ) -> Result<Vec<FetchedFile>> {
+        let repo = repo.to_string();
+        let family = self.get_repo_family(&repo).unwrap_or_else(|| self.family.clone());
+        let archive_members = self.get_repo_archive_members(&repo);
+        let hashes = self.get_repo_hashes(&repo).unwrap_or_default();
+        let options = self
+            .repo_font
+            .get(&repo)
+            .map(|repo_font| repo_font.http.clone())
+            .unwrap_or_default();
+
+        let mut transaction = Transaction::new(download_path)?;
+        let mut variants: Vec<(String, String)> = Vec::new();
+        let extract_dir = tempfile::tempdir()?;
+
+        let files = self.selected_files(&repo, selection)?;
+
+        // Plain files are downloaded straight into their staging path, archives
+        // are downloaded once into a temporary folder and extracted afterwards
+        let mut transfers: Vec<(String, PathBuf, Option<String>)> = Vec::new();
+        let mut archives: HashMap<String, PathBuf> = HashMap::new();
+        for (variant, file) in files.iter() {
+            if !archive::is_archive(file) {
+                let extension = archive::url_extension(file);
+                let file_name = self.output_file_name(&repo, variant, &extension);
+                if output {
+                    println!("Downloading {} from {}", &file_name, file);
+                }
+                transfers.push((
+                    file.clone(),
+                    transaction.stage(&file_name),
+                    hashes.get(variant).cloned(),
+                ));
+                variants.push((variant.clone(), file.clone()));
+            } else if !archives.contains_key(file) {
+                if output {
+                    println!("Downloading archive {}", file);
                 }
+                // Variants sharing an archive may carry its hash on any of them
+                let hash = files
+                    .iter()
+                    .filter(|(_, url)| url == file)
+                    .find_map(|(variant, _)| hashes.get(variant));
+                let archive_file = extract_dir.path().join(format!("{}.archive", transfers.len()));
+                archives.insert(file.clone(), archive_file.clone());
+                transfers.push((file.clone(), archive_file, hash.cloned()));
+            }
+        }
+
+        for result in parallel::map(&transfers, |(url, output_file, hash)| {
+            download_file(output_file, url, hash.as_deref(), &options)
+        }) {
+            result?;
+        }
+        let mut content_hashes: HashMap<String, String> = HashMap::new();
+        for (url, output_file, _hash) in transfers.iter() {
+            let hash = checksum::sha256_hex(&fs::read(output_file)?);
+            content_hashes.insert(url.clone(), format!("sha256:{}", hash));
+        }
+
+        let mut extracted: HashMap<String, (PathBuf, Vec<PathBuf>)> = HashMap::new();
+        for (variant, file) in files.iter().filter(|(_, file)| archive::is_archive(file)) {
+            if !extracted.contains_key(file) {
+                let dir = extract_dir.path().join(extracted.len().to_string());
+                let data = fs::read(&archives[file])?;
+                let members = archive::extract_fonts(file, &data, &dir)?;
+                extracted.insert(file.clone(), (dir, members));
+            }
+            let (dir, members) = &extracted[file];
+            let member = archive::find_member(
+                members,
+                dir,
+                &family,
+                variant,
+                archive_members.get(variant).map(String::as_str),
+            )?
+            .ok_or_else(|| Error::NotFound(format!("{} {} in {}", family, variant, file)))?;
+
+            let file_name = self.output_file_name(&repo, variant, &archive::extension(&member));
+            if output {
+                println!("Extracting {} from {}", &file_name, file);
             }
-            None => {}
+            archive::copy_member(&member, &transaction.stage(&file_name))?;
+            variants.push((variant.clone(), file.clone()));
         }
-        Ok(())
+        let mut fetched = Vec::new();
+        transaction.commit(|paths| {
+            fetched = variants
+                .into_iter()
+                .zip(paths.iter().cloned())
+                .map(|((variant, url), path)| FetchedFile {
+                    hash: content_hashes[&url].clone(),
+                    variant,
+                    url,
+                    path,
+                })
+                .collect();
+            finish(&fetched)
+        })?;
+        Ok(fetched)
     }
 
+    /// Paths `download` would write to. For variants inside archives the
+    /// extension is taken from the catalog's glob, defaulting to .ttf.
     pub fn output_paths(
         &self,
         repo: Option<&str>,
-        path: &PathBuf
+        path: &Path
     ) -> Vec<PathBuf> {
-        let repos = self.get_first_available_repo();
-        let repo = match repo {
-            Some(repo) => repo,
-            None => match &repos {
-                Some(repo) => repo,
-                None => "",
-            },
-        };
-
         let mut results: Vec<PathBuf> = Vec::new();
 
-        match self.get_repo_files(repo) {
-            Some(files) => {
+        if let Ok(repo) = self.resolve_repo(repo) {
+            let archive_members = self.get_repo_archive_members(&repo);
+            if let Some(files) = self.get_repo_files(&repo) {
                 for (variant, file) in files {
-                    let extension: &str = file.split(".").collect::<Vec<&str>>().last().unwrap();
-                    results.push(
-                        path.join(&format!(
-                            "{}-{}.{}",
-                            &self.get_repo_family(repo).unwrap(),
-                            &variant,
-                            &extension
-                        ))
-                    );
+                    results.push(self.output_path(&repo, &variant, &file, &archive_members, path));
                 }
             }
-            None => {}
         }
 
         results
     }
 
-    pub fn install_to_user(&mut self, repo: Option<&str>, output: bool) -> Result<()> {
-        let install_dir = font_dir().unwrap();
+    fn output_path(
+        &self,
+        repo: &str,
+        variant: &str,
+        file: &str,
+        archive_members: &HashMap<String, String>,
+        path: &Path,
+    ) -> PathBuf {
+        let extension = if archive::is_archive(file) {
+            archive_members
+                .get(variant)
+                .map(|pattern| archive::extension(Path::new(pattern)))
+                .unwrap_or_else(|| "ttf".to_string())
+        } else {
+            archive::url_extension(file)
+        };
+        path.join(self.output_file_name(repo, variant, &extension))
+    }
+
+    /// What `download` would fetch from `repo` and write into `download_path`.
+    pub fn plan_download(
+        &self,
+        repo: Option<&str>,
+        download_path: &Path,
+        lock: Option<&LockSettings>,
+    ) -> Result<Plan> {
+        self.plan_fetch(repo, None, download_path, lock)
+    }
+
+    /// What `download_variants` would fetch and write.
+    pub fn plan_download_variants(
+        &self,
+        repo: Option<&str>,
+        variants: &[&str],
+        download_path: &Path,
+        lock: Option<&LockSettings>,
+    ) -> Result<Plan> {
+        let selection = to_selection(variants);
+        self.plan_fetch(repo, Some(&selection), download_path, lock)
+    }
+
+    pub(crate) fn plan_fetch(
+        &self,
+        repo: Option<&str>,
+        selection: Option<&[String]>,
+        download_path: &Path,
+        lock: Option<&LockSettings>,
+    ) -> Result<Plan> {
+        let (font, repo) = self.fetch_source(repo, selection, lock)?;
+        let files = font.selected_files(&repo, selection)?;
+        let archive_members = font.get_repo_archive_members(&repo);
+        let mut writes: Vec<PathBuf> = files
+            .iter()
+            .map(|(variant, file)| {
+                font.output_path(&repo, variant, file, &archive_members, download_path)
+            })
+            .collect();
+        writes.sort();
+
+        let mut downloads: Vec<PlannedDownload> = Vec::new();
+        for (variant, file) in files {
+            match downloads.iter_mut().find(|download| download.url == file) {
+                Some(download) => download.variants.push(variant),
+                None => downloads.push(PlannedDownload {
+                    archive: archive::is_archive(&file),
+                    url: file,
+                    variants: vec![variant],
+                }),
+            }
+        }
+        Ok(Plan {
+            family: self.family.clone(),
+            repo: Some(repo),
+            location: None,
+            downloads,
+            writes,
+            removals: Vec::new(),
+        })
+    }
+
+    /// What installing the font from `repo` into `location` would do. Files
+    /// of a previous install that the new one doesn't replace are removed.
+    pub fn plan_install(
+        &self,
+        repo: Option<&str>,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+        lock: Option<&LockSettings>,
+    ) -> Result<Plan> {
+        self.plan_install_selection(repo, None, location, installed_fonts, lock)
+    }
+
+    /// What `install_variants` would do.
+    pub fn plan_install_variants(
+        &self,
+        repo: Option<&str>,
+        variants: &[&str],
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+        lock: Option<&LockSettings>,
+    ) -> Result<Plan> {
+        let repo = self.fetch_repo(repo, lock)?;
+        let selection =
+            self.install_selection(&repo, &to_selection(variants), location, installed_fonts);
+        let selection = selection.as_deref();
+        self.plan_install_selection(Some(&repo), selection, location, installed_fonts, lock)
+    }
+
+    /// What `update` would do.
+    pub fn plan_update(
+        &self,
+        repo: Option<&str>,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+        lock: Option<&LockSettings>,
+    ) -> Result<Plan> {
+        let repo = self.fetch_repo(repo, lock)?;
+        let selection = self.update_selection(&repo, location, installed_fonts);
+        let selection = selection.as_deref();
+        self.plan_install_selection(Some(&repo), selection, location, installed_fonts, lock)
+    }
+
+    /// Variants installed in `location` that `repo` still offers, or `None`
+    /// if every variant was installed.
+    fn update_selection(
+        &self,
+        repo: &str,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+    ) -> Option<Vec<String>> {
+        let mut selection = installed_fonts.get(&self.family, location)?.variants.clone()?;
+        let offered = self.get_repo_variants(repo).unwrap_or_default();
+        selection.retain(|variant| offered.contains(variant));
+        Some(selection)
+    }
+
+    /// `variants` along with the ones already installed in `location`, so
+    /// installing more variants keeps the others. `None` if every variant is
+    /// installed already.
+    fn install_selection(
+        &self,
+        repo: &str,
+        variants: &[String],
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+    ) -> Option<Vec<String>> {
+        let mut selection = variants.to_vec();
+        if installed_fonts.is_installed(&self.family, location) {
+            selection.extend(self.update_selection(repo, location, installed_fonts)?);
+        }
+        selection.sort();
+        selection.dedup();
+        Some(selection)
+    }
+
+    fn plan_install_selection(
+        &self,
+        repo: Option<&str>,
+        selection: Option<&[String]>,
+        location: &Location,
+        installed_fonts: &InstalledFonts,
+        lock: Option<&LockSettings>,
+    ) -> Result<Plan> {
+        let mut plan = self.plan_fetch(repo, selection, &install_dir(location)?, lock)?;
+        if let Some(previous) = installed_fonts.get(&self.family, location) {
+            plan.removals = previous
+                .files
+                .iter()
+                .map(|file| file.path.clone())
+                .filter(|path| !plan.writes.contains(path))
+                .collect();
+        }
+        plan.location = Some(location.clone());
+        Ok(plan)
+    }
+
+    fn install_to(
+        &mut self,
+        repo: Option<&str>,
+        selection: Option<Vec<String>>,
+        location: Location,
+        lock: Option<&LockSettings>,
+        output: bool,
+    ) -> Result<Vec<PathBuf>> {
+        let (source, repo) = self.fetch_source(repo, selection.as_deref(), lock)?;
+        // A locked install only fetches the variants locked, so later updates
+        // have to keep to them
+        let selection = match selection {
+            None if lock.is_some_and(|lock| lock.locked) => {
+                source.get_repo_variants(&repo)
+            }
+            selection => selection,
+        };
+        let install_dir = install_dir(&location)?;
+        // Loaded first, so a broken manifest fails before anything is written
+        let mut installed_fonts = InstalledFonts::load()?;
+        let previous_files: Vec<PathBuf> = installed_fonts
+            .get(&self.family, &location)
+            .map(|previous| previous.files.iter().map(|file| file.path.clone()).collect())
+            .unwrap_or_default();
+        let record = InstallRecord {
+            family: self.family.clone(),
+            location,
+            repo: repo.clone(),
+            version: source.get_repo_version(&repo),
+            last_modified: source
+                .repo_font
+                .get(&repo)
+                .and_then(|repo_font| repo_font.last_modified.clone()),
+            files: Vec::new(),
+            variants: selection.clone(),
+            installed_at: Utc::now().to_rfc3339(),
+        };
+        // The manifest is saved before the install is committed, so the files
+        // are moved back out if that fails
+        let written = self.fetch_variants(
+            Some(&repo),
+            selection.as_deref(),
+            &install_dir,
+            lock,
+            output,
+            |fetched| {
+                let mut files = Vec::new();
+                for file in fetched.iter() {
+                    files.push(InstalledFile {
+                        variant: file.variant.clone(),
+                        path: file.path.clone(),
+                        sha256: checksum::sha256_hex(&fs::read(&file.path)?),
+                    });
+                }
+                installed_fonts.record(InstallRecord { files, ..record });
+                installed_fonts.save()
+            },
+        )?;
 
-        self.download(repo.clone(), &install_dir, output)?;
+        // Files of a previous install that this one did not overwrite are stale
+        for path in previous_files.iter() {
+            if !written.iter().any(|(_variant, written)| written == path) {
+                let _ = fs::remove_file(path);
+            }
+        }
 
         let new_local_font = generate_local_font_from_handles(
-            &self.output_paths(repo, &install_dir).iter().map(
-                |path| {
+            &written.iter().map(
+                |(_variant, path)| {
                     Handle::from_path(path.to_path_buf(), 0)
                 }).collect::<Vec<Handle>>()
         );
@@ -762,6 +1809,168 @@ impl Font {
             new_local_font.0.clone(), new_local_font.1
         );
 
-        Ok(())
+        Ok(written.into_iter().map(|(_variant, path)| path).collect())
+    }
+
+    /// Installs the font for the current user and returns the files written.
+    /// `lock` works as in `download`.
+    pub fn install_to_user(
+        &mut self,
+        repo: Option<&str>,
+        lock: Option<&LockSettings>,
+        output: bool,
+    ) -> Result<Vec<PathBuf>> {
+        self.install_to(repo, None, Location::User, lock, output)
+    }
+
+    /// Installs the font for every user and returns the files written.
+    pub fn install_to_system(
+        &mut self,
+        repo: Option<&str>,
+        lock: Option<&LockSettings>,
+        output: bool,
+    ) -> Result<Vec<PathBuf>> {
+        self.install_to(repo, None, Location::System, lock, output)
+    }
+
+    /// Installs only `variants` of the font in `location`, e.g.
+    /// `&["regular", "700"]`, and returns the files written. Variants already
+    /// installed there are kept and fetched again from the same version.
+    pub fn install_variants(
+        &mut self,
+        repo: Option<&str>,
+        variants: &[&str],
+        location: &Location,
+        lock: Option<&LockSettings>,
+        output: bool,
+    ) -> Result<Vec<PathBuf>> {
+        let repo = self.fetch_repo(repo, lock)?;
+        let installed_fonts = InstalledFonts::load()?;
+        let selection =
+            self.install_selection(&repo, &to_selection(variants), location, &installed_fonts);
+        self.install_to(Some(&repo), selection, location.clone(), lock, output)
+    }
+
+    /// Installs the font from `repo` again, keeping the variants previously
+    /// installed in `location`, and returns the files written.
+    pub fn update(
+        &mut self,
+        repo: Option<&str>,
+        location: &Location,
+        lock: Option<&LockSettings>,
+        output: bool,
+    ) -> Result<Vec<PathBuf>> {
+        let repo = self.fetch_repo(repo, lock)?;
+        let installed_fonts = InstalledFonts::load()?;
+        let selection = self.update_selection(&repo, location, &installed_fonts);
+        self.install_to(Some(&repo), selection, location.clone(), lock, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use font_kit::family_name::FamilyName;
+    use font_kit::properties::Properties;
+
+    use super::*;
+
+    /// Any font file installed on the machine, to serve as a repository file.
+    pub(crate) fn system_font_file() -> Option<PathBuf> {
+        let handle = SystemSource::new()
+            .select_best_match(&[FamilyName::SansSerif], &Properties::new())
+            .ok()?;
+        match handle {
+            Handle::Path { path, .. } => Some(path),
+            Handle::Memory { .. } => None,
+        }
+    }
+
+    fn font(url: &str) -> Font {
+        let repo_font = RepoFont {
+            family: Some("Test Sans".to_string()),
+            variants: vec!["regular".to_string(), "700".to_string()],
+            version: Some("v2".to_string()),
+            files: vec![
+                ("regular".to_string(), url.to_string()),
+                ("700".to_string(), url.to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            ..RepoFont::default()
+        };
+        let repos = vec![("Test".to_string(), vec![repo_font])].into_iter().collect();
+        generate_fonts_list(repos, Vec::new()).get("Test Sans").unwrap().clone()
+    }
+
+    fn lockfile(url: &str, hash: &str) -> Lockfile {
+        let mut lockfile = Lockfile::default();
+        lockfile.record(LockedFont {
+            family: "Test Sans".to_string(),
+            repo: "Test".to_string(),
+            version: Some("v1".to_string()),
+            last_modified: None,
+            variants: vec![LockedVariant {
+                variant: "regular".to_string(),
+                url: url.to_string(),
+                hash: hash.to_string(),
+                archive_member: None,
+            }],
+        });
+        lockfile
+    }
+
+    #[test]
+    fn locked_copy_offers_only_what_is_locked() {
+        let font = font("https://example.com/v2.ttf");
+        let lockfile = lockfile("https://example.com/v1.ttf", "sha256:00");
+        let (locked, repo) = font.locked_copy(None, None, &lockfile).unwrap();
+        assert_eq!(repo, "Test");
+        assert_eq!(locked.get_repo_version("Test").as_deref(), Some("v1"));
+        assert_eq!(locked.get_repo_variants("Test"), Some(vec!["regular".to_string()]));
+        let files = locked.get_repo_files("Test").unwrap();
+        assert_eq!(files.get("regular").map(String::as_str), Some("https://example.com/v1.ttf"));
+        assert_eq!(
+            locked.get_repo_hashes("Test").unwrap().get("regular").map(String::as_str),
+            Some("sha256:00")
+        );
+    }
+
+    #[test]
+    fn locked_copy_rejects_what_isnt_locked() {
+        let font = font("https://example.com/v2.ttf");
+        let lockfile = lockfile("https://example.com/v1.ttf", "sha256:00");
+        let selection = vec!["700".to_string()];
+        assert!(matches!(
+            font.locked_copy(None, Some(&selection), &lockfile),
+            Err(Error::Locked(_))
+        ));
+        assert!(matches!(
+            font.locked_copy(Some("Other"), None, &lockfile),
+            Err(Error::Locked(_))
+        ));
+        assert!(matches!(
+            font.locked_copy(None, None, &Lockfile::default()),
+            Err(Error::Locked(_))
+        ));
+    }
+
+    #[test]
+    fn locked_download_fails_when_a_file_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let font_file = dir.path().join("font.ttf");
+        fs::write(&font_file, b"changed since it was locked").unwrap();
+        let url = format!("file://{}", font_file.display());
+        let settings = LockSettings {
+            path: dir.path().join(LOCK_FILE),
+            locked: true,
+        };
+        lockfile(&url, &format!("sha256:{}", checksum::sha256_hex(b"as locked")))
+            .save(&settings.path)
+            .unwrap();
+
+        let target = dir.path().join("fonts");
+        let result = font(&url).download(None, &target, Some(&settings), false);
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+        assert!(!target.exists());
     }
 }
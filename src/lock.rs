@@ -0,0 +1,172 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{write_atomic, Error, Result};
+
+pub const LOCK_FILE: &str = "fonts.lock";
+
+/// A variant as it was fetched.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockedVariant {
+    pub variant: String,
+    pub url: String,
+    /// Hash of the content downloaded from `url`, as `sha256:<hex>`.
+    pub hash: String,
+    /// Glob selecting the variant inside the archive at `url`.
+    #[serde(
+        rename = "archiveMember",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archive_member: Option<String>,
+}
+
+/// A family as it was fetched, from a single repository and version.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockedFont {
+    pub family: String,
+    pub repo: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(
+        rename = "lastModified",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_modified: Option<String>,
+    #[serde(rename = "variant", default)]
+    pub variants: Vec<LockedVariant>,
+}
+
+/// The exact files fetched for each family, stored as `fonts.lock`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Lockfile {
+    #[serde(rename = "font", default)]
+    pub fonts: Vec<LockedFont>,
+}
+
+impl Lockfile {
+    /// Reads the lockfile at `path`, empty if there is none yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).map_err(|e| Error::Parse(e.to_string()))?;
+        write_atomic(path, contents.as_bytes())
+    }
+
+    pub fn get(&self, family: &str) -> Option<&LockedFont> {
+        self.fonts.iter().find(|font| font.family == family)
+    }
+
+    /// Adds the variants of `font`. Variants locked before are kept if they
+    /// come from the same repository and version, and dropped otherwise.
+    pub fn record(&mut self, mut font: LockedFont) {
+        if let Some(index) = self
+            .fonts
+            .iter()
+            .position(|locked| locked.family == font.family)
+        {
+            let previous = self.fonts.remove(index);
+            if previous.repo == font.repo
+                && previous.version == font.version
+                && previous.last_modified == font.last_modified
+            {
+                for variant in previous.variants {
+                    if !font
+                        .variants
+                        .iter()
+                        .any(|locked| locked.variant == variant.variant)
+                    {
+                        font.variants.push(variant);
+                    }
+                }
+            }
+        }
+        font.variants.sort_by(|a, b| a.variant.cmp(&b.variant));
+        self.fonts.push(font);
+        self.fonts.sort_by(|a, b| a.family.cmp(&b.family));
+    }
+}
+
+/// Where fonts are locked, and whether to install only what is locked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockSettings {
+    pub path: PathBuf,
+    /// Fetch exactly the files recorded in the lockfile, failing for fonts
+    /// or variants it doesn't have and for files whose content changed.
+    pub locked: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(variant: &str) -> LockedVariant {
+        LockedVariant {
+            variant: variant.to_string(),
+            url: format!("https://example.com/{}.ttf", variant),
+            hash: format!("sha256:{}", variant),
+            archive_member: None,
+        }
+    }
+
+    fn font(version: &str, variants: &[&str]) -> LockedFont {
+        LockedFont {
+            family: "Test Sans".to_string(),
+            repo: "Test".to_string(),
+            version: Some(version.to_string()),
+            last_modified: None,
+            variants: variants.iter().map(|name| variant(name)).collect(),
+        }
+    }
+
+    fn variant_names(lockfile: &Lockfile) -> Vec<&str> {
+        lockfile
+            .get("Test Sans")
+            .unwrap()
+            .variants
+            .iter()
+            .map(|locked| locked.variant.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn record_keeps_variants_of_the_same_version() {
+        let mut lockfile = Lockfile::default();
+        lockfile.record(font("v1", &["regular"]));
+        lockfile.record(font("v1", &["700"]));
+        assert_eq!(lockfile.fonts.len(), 1);
+        assert_eq!(variant_names(&lockfile), vec!["700", "regular"]);
+    }
+
+    #[test]
+    fn record_drops_variants_of_another_version() {
+        let mut lockfile = Lockfile::default();
+        lockfile.record(font("v1", &["regular", "700"]));
+        lockfile.record(font("v2", &["regular"]));
+        assert_eq!(variant_names(&lockfile), vec!["regular"]);
+        assert_eq!(
+            lockfile.get("Test Sans").unwrap().version.as_deref(),
+            Some("v2")
+        );
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE);
+        assert_eq!(Lockfile::load(&path).unwrap(), Lockfile::default());
+
+        let mut lockfile = Lockfile::default();
+        lockfile.record(font("v1", &["regular"]));
+        lockfile.save(&path).unwrap();
+        assert_eq!(Lockfile::load(&path).unwrap(), lockfile);
+    }
+}
@@ -1,14 +1,22 @@
-use std::collections::HashMap;
-use std::env::args;
-use std::fs::{read_dir, File};
-use std::io::{Result, Write};
-use std::path::PathBuf;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
+use chrono::{DateTime, Utc};
+use clap::{CommandFactory, Parser};
+use clap_complete::env::Shells;
+use clap_complete::CompleteEnv;
 use dirs::data_dir;
-use serde_json::json;
+use serde_json::{json, Map, Value};
 
-mod lib;
+use font_catcher as lib;
+use lib::{Error, Result};
+
+mod cli;
+mod output;
+use cli::{Cli, Command, Shell};
+use output::{Format, Output};
 
 fn print_version() {
     println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
@@ -19,287 +27,886 @@ fn print_version() {
     println!("This is free software. It is licensed for use, modification and");
     println!("redistribution under the terms of the GNU Affero General Public License,");
     println!("version 3. <https://www.gnu.org/licenses/agpl-3.0.en.html>");
-    println!("");
+    println!();
     println!("{}", env!("CARGO_PKG_DESCRIPTION"));
 }
 
-struct Cli {
-    location: Option<lib::Location>,
-    command: String,
-    repo: Option<String>,
-    path: PathBuf,
-    fonts: Vec<String>,
-    use_local_repos: bool,
+fn location_name(location: &lib::Location) -> &'static str {
+    match location {
+        lib::Location::User => "user",
+        lib::Location::System => "system",
+        lib::Location::Memory => "memory",
+    }
+}
+
+fn not_found(font: &str) -> Error {
+    Error::NotFound(font.to_string())
+}
+
+/// Prints `error` in text mode and returns its message for the records.
+fn report_error(failed: &mut bool, text: bool, name: &str, error: Error) -> String {
+    *failed = true;
+    if text {
+        match error {
+            Error::NotFound(ref what) if what == name => println!("{} not found anywhere!", name),
+            ref error => eprintln!("error: {}: {}", name, error),
+        }
+    }
+    error.to_string()
+}
+
+fn font_record(font: &mut lib::Font) -> Value {
+    let mut repos = font.get_repos_availability().unwrap_or_default();
+    repos.sort();
+    let mut variants: Vec<String> = Vec::new();
+    let mut subsets: Vec<String> = Vec::new();
+    let mut versions = Map::new();
+    let mut category = None;
+    for repo in repos.iter() {
+        category = category.or_else(|| font.get_repo_category(repo));
+        variants.extend(font.get_repo_variants(repo).unwrap_or_default());
+        subsets.extend(font.get_repo_subsets(repo).unwrap_or_default());
+        versions.insert(repo.clone(), json!(font.get_repo_version(repo)));
+    }
+    variants.sort();
+    variants.dedup();
+    subsets.sort();
+    subsets.dedup();
+
+    let mut installed = Vec::new();
+    if font.is_font_user_installed() {
+        installed.push(location_name(&lib::Location::User));
+    }
+    if font.is_font_system_installed() {
+        installed.push(location_name(&lib::Location::System));
+    }
+    json!({
+        "family": font.get_family(),
+        "repos": repos,
+        "variants": variants,
+        "subsets": subsets,
+        "category": category,
+        "versions": versions,
+        "installed": installed,
+    })
+}
+
+/// Everything known about a font: what each repository offers, the files
+/// installed for the user and the system, and the variants that differ.
+fn info_record(font: &mut lib::Font, installed_fonts: &lib::InstalledFonts) -> Value {
+    let mut repos = font.get_repos_availability().unwrap_or_default();
+    repos.sort();
+    let locations = [lib::Location::User, lib::Location::System];
+
+    let mut repo_records = Vec::new();
+    for repo in repos.iter() {
+        let mut variants = font.get_repo_variants(repo).unwrap_or_default();
+        variants.sort();
+        repo_records.push(json!({
+            "name": repo,
+            "version": font.get_repo_version(repo),
+            "lastModified": font
+                .get_repo_last_modified(repo)
+                .map(|date| date.format("%Y-%m-%d").to_string()),
+            "variants": variants,
+            "subsets": font.get_repo_subsets(repo).unwrap_or_default(),
+            "category": font.get_repo_category(repo),
+            "creator": font.get_repo_creator(repo),
+            "commentary": font.get_repo_commentary(repo),
+        }));
+    }
+
+    let mut installed = Vec::new();
+    let mut differences = Vec::new();
+    for location in locations.iter() {
+        let mut files: Vec<(String, PathBuf)> = font
+            .get_local_variant_files(location, installed_fonts)
+            .into_iter()
+            .flat_map(|(variant, paths)| paths.into_iter().map(move |path| (variant.clone(), path)))
+            .collect();
+        if files.is_empty() {
+            continue;
+        }
+        files.sort();
+        let files: Vec<Value> = files
+            .into_iter()
+            .map(|(variant, path)| {
+                let modified = fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .map(|time| DateTime::<Utc>::from(time).format("%Y-%m-%d %H:%M:%S").to_string());
+                json!({ "variant": variant, "path": path, "modified": modified })
+            })
+            .collect();
+        installed.push(json!({
+            "location": location_name(location),
+            "managed": installed_fonts.is_installed(&font.get_family(), location),
+            "files": files,
+        }));
+
+        for repo in repos.iter() {
+            let (missing, extra) = font.get_variant_differences(repo, location, installed_fonts);
+            if !missing.is_empty() || !extra.is_empty() {
+                differences.push(json!({
+                    "repo": repo,
+                    "location": location_name(location),
+                    "missing": missing,
+                    "extra": extra,
+                }));
+            }
+        }
+    }
+
+    json!({
+        "family": font.get_family(),
+        "repos": repo_records,
+        "installed": installed,
+        "differences": differences,
+    })
 }
 
-fn run() -> Result<()> {
-    let args: Vec<String> = args().collect();
-    let mut clean_args: Vec<String> = Vec::new();
-
-    let mut cli = Cli {
-        location: None,
-        command: "version".to_string(),
-        repo: None,
-        use_local_repos: true,
-        path: PathBuf::from("."),
-        fonts: Vec::new(),
+fn print_info(record: &Value) {
+    let list = |value: &Value| -> String {
+        value
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<&str>>()
+                    .join(" ")
+            })
+            .unwrap_or_default()
     };
+    let optional = |value: &Value| value.as_str().unwrap_or("unknown").to_string();
 
-    let mut skip: bool = false;
-    if args.len() > 1 {
-        for i in 0..(args.len()) {
-            match args[i].as_str() {
-                "--repo" => {
-                    cli.repo = Some(args[i + 1].clone());
-                    skip = true;
-                }
-                "--user" => {
-                    cli.location = Some(lib::Location::User);
-                }
-                "--path" => {
-                    cli.path = PathBuf::from(&args[i + 1]);
-                    skip = true;
-                }
-                "--system" => {
-                    cli.location = Some(lib::Location::System);
-                }
-                "--use-preinstalled-repos" => {
-                    cli.use_local_repos = false;
-                }
-                _ => {
-                    if !skip {
-                        clean_args.push(args[i].clone());
-                    }
-                    skip = false;
-                }
+    println!("{}", record["family"].as_str().unwrap_or_default());
+    for repo in record["repos"].as_array().into_iter().flatten() {
+        println!("\n  {}:", repo["name"].as_str().unwrap_or_default());
+        println!("    Version: {}", optional(&repo["version"]));
+        println!("    Last modified: {}", optional(&repo["lastModified"]));
+        println!("    Variants: {}", list(&repo["variants"]));
+        println!("    Subsets: {}", list(&repo["subsets"]));
+        println!("    Category: {}", optional(&repo["category"]));
+        println!("    Creator: {}", optional(&repo["creator"]));
+        if let Some(commentary) = repo["commentary"].as_str() {
+            println!("    Commentary: {}", commentary);
+        }
+    }
+    for installed in record["installed"].as_array().into_iter().flatten() {
+        println!(
+            "\n  Installed for the {}{}:",
+            installed["location"].as_str().unwrap_or_default(),
+            if installed["managed"].as_bool() == Some(true) { " by font-catcher" } else { "" }
+        );
+        for file in installed["files"].as_array().into_iter().flatten() {
+            println!(
+                "    {}: {} ({})",
+                file["variant"].as_str().unwrap_or_default(),
+                file["path"].as_str().unwrap_or_default(),
+                optional(&file["modified"])
+            );
+        }
+    }
+    let differences = record["differences"].as_array().cloned().unwrap_or_default();
+    if !differences.is_empty() {
+        println!("\n  Variant differences:");
+        for difference in differences.iter() {
+            println!(
+                "    {} / {}:",
+                difference["repo"].as_str().unwrap_or_default(),
+                difference["location"].as_str().unwrap_or_default()
+            );
+            if !list(&difference["missing"]).is_empty() {
+                println!("      Not installed: {}", list(&difference["missing"]));
+            }
+            if !list(&difference["extra"]).is_empty() {
+                println!("      Not in the repository: {}", list(&difference["extra"]));
             }
         }
-    } else {
-        print_version();
-        return Ok(());
     }
-    cli.command = clean_args[1].clone();
-    cli.fonts = clean_args[2..].to_vec();
+}
 
-    let font_catcher_dir = data_dir().unwrap().join("font-catcher");
-    let repos_dir = font_catcher_dir.join("repos");
-    let repos_file = font_catcher_dir.join("repos.conf");
+/// Splits `Family:variant,variant` into the family and its variants. A
+/// family whose name contains `:` is taken as is.
+fn parse_font_spec<'a>(fonts_list: &lib::Catalog, spec: &'a str) -> (&'a str, Option<Vec<&'a str>>) {
+    match spec.rsplit_once(':') {
+        Some((family, variants)) if fonts_list.get(spec).is_none() => {
+            let variants: Vec<&str> = variants
+                .split(',')
+                .map(str::trim)
+                .filter(|variant| !variant.is_empty())
+                .collect();
+            (family, Some(variants).filter(|variants| !variants.is_empty()))
+        }
+        _ => (spec, None),
+    }
+}
+
+fn action_record(
+    action: &str,
+    family: &str,
+    location: Option<&lib::Location>,
+    repo: Option<&str>,
+) -> Value {
+    json!({
+        "action": action,
+        "family": family,
+        "location": location.map(location_name),
+        "repo": repo,
+    })
+}
 
-    let local_repos_file: Vec<lib::Repository> = lib::generate_repos_from_file(&repos_file)?;
+/// Completes an action record with the files written or removed, or with
+/// the error that stopped the action.
+fn finish_action(
+    mut record: Value,
+    result: Result<Vec<PathBuf>>,
+    failed: &mut bool,
+    text: bool,
+) -> Value {
+    let family = record["family"].as_str().unwrap_or_default().to_string();
+    let (files, error) = match result {
+        Ok(files) => (files, None),
+        Err(e) => (Vec::new(), Some(report_error(failed, text, &family, e))),
+    };
+    record["files"] = json!(files);
+    record["error"] = json!(error);
+    record
+}
 
-    let mut local_repos: HashMap<String, Vec<lib::RepoFont>> = HashMap::new();
+fn print_plan(action: &str, plan: &lib::Plan) {
+    print!("Would {} {}", action, plan.family);
+    if let Some(repo) = &plan.repo {
+        print!(" from {}", repo);
+    }
+    if let Some(location) = &plan.location {
+        print!(" for the {}", location_name(location));
+    }
+    println!(":");
+    for download in plan.downloads.iter() {
+        println!(
+            "  Download {}{} ({})",
+            if download.archive { "archive " } else { "" },
+            download.url,
+            download.variants.join(" ")
+        );
+    }
+    for path in plan.writes.iter() {
+        println!("  Write {}", path.display());
+    }
+    for path in plan.removals.iter() {
+        println!("  Remove {}", path.display());
+    }
+}
 
-    for file in read_dir(&repos_dir)? {
-        let file = file.unwrap();
-        match lib::generate_repo_font_list_from_file(&file.path()) {
-            Ok(fonts_list) => {
-                local_repos.insert(file.file_name().into_string().unwrap(), fonts_list);
+/// Completes an action record with what the action would do, for
+/// `--dry-run`.
+fn finish_plan(
+    mut record: Value,
+    result: Result<lib::Plan>,
+    failed: &mut bool,
+    text: bool,
+) -> Value {
+    let family = record["family"].as_str().unwrap_or_default().to_string();
+    match result {
+        Ok(plan) => {
+            if text && !plan.is_empty() {
+                print_plan(record["action"].as_str().unwrap_or_default(), &plan);
             }
-            Err(_) => {
-                eprintln!("Error while reading repo...");
+            if plan.repo.is_some() {
+                record["repo"] = json!(plan.repo);
             }
+            record["downloads"] = json!(plan.downloads);
+            record["writes"] = json!(plan.writes);
+            record["removals"] = json!(plan.removals);
+            record["error"] = Value::Null;
+        }
+        Err(e) => {
+            record["error"] = json!(report_error(failed, text, &family, e));
+        }
+    }
+    record
+}
+
+fn print_completions(shell: Shell) -> Result<()> {
+    let name = env!("CARGO_PKG_NAME");
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell.name())
+        .ok_or_else(|| Error::NotFound(shell.name().to_string()))?;
+    let result = completer.write_registration("COMPLETE", name, name, name, &mut io::stdout());
+    Ok(ignore_broken_pipe(result)?)
+}
+
+fn print_man_page() -> Result<()> {
+    let result = clap_mangen::Man::new(Cli::command()).render(&mut io::stdout());
+    Ok(ignore_broken_pipe(result)?)
+}
+
+/// Output piped into a command that stops reading early, like `head`, isn't
+/// an error.
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Lockfile of the commands fetching fonts: `--lockfile`, or else `fonts.lock`
+/// next to the `fonts.toml` of the project in the current folder, or in the
+/// data folder outside of a project.
+fn lock_settings(
+    lockfile: Option<&Path>,
+    locked: bool,
+    font_catcher_dir: &Path,
+) -> Result<lib::LockSettings> {
+    let path = match lockfile {
+        Some(path) => path.to_path_buf(),
+        None => match lib::Project::find(&std::env::current_dir()?) {
+            Some(manifest) => manifest.with_file_name(lib::LOCK_FILE),
+            None => font_catcher_dir.join(lib::LOCK_FILE),
+        },
+    };
+    Ok(lib::LockSettings { path, locked })
+}
+
+fn run() -> Result<bool> {
+    let cli = Cli::parse();
+    let format = cli.format();
+
+    let command = match cli.command {
+        None | Some(Command::Version) if format == Format::Text => {
+            print_version();
+            return Ok(true);
+        }
+        None => Command::Version,
+        Some(Command::Completions { shell }) => {
+            print_completions(shell)?;
+            return Ok(true);
+        }
+        Some(Command::Man) => {
+            print_man_page()?;
+            return Ok(true);
+        }
+        Some(command) => command,
+    };
+    if let Some(jobs) = cli.jobs {
+        lib::set_jobs(jobs);
+    }
+
+    let font_catcher_dir = data_dir()
+        .ok_or_else(|| Error::NotFound("data directory".to_string()))?
+        .join("font-catcher");
+    let repos_dir = font_catcher_dir.join("repos");
+    let repos_file = font_catcher_dir.join("repos.conf");
+
+    let local_repos_file: Vec<lib::Repository> = match lib::generate_repos_from_file(&repos_file) {
+        Ok(repos) => repos,
+        Err(Error::Parse(e)) => {
+            eprintln!("error: {}", e);
+            eprintln!("Skipping reading from local repositories");
+            Vec::new()
         }
+        Err(e) => return Err(e),
+    };
+
+    if let Ok(network_config) = lib::generate_network_config_from_file(&repos_file) {
+        lib::set_network_config(network_config);
+    }
+
+    let repo_caches = lib::read_repo_caches(&repos_dir)?;
+    for (path, e) in repo_caches.skipped.iter() {
+        eprintln!("warning: skipping {}: {}", path.display(), e);
     }
+    let mut local_repos = repo_caches.repos;
+    lib::apply_repository_options(&mut local_repos, &local_repos_file);
 
-    let fonts_list = match cli.use_local_repos {
-        true => lib::generate_fonts_list(
+    let mut fonts_list = match cli.use_preinstalled_repos {
+        false => lib::generate_fonts_list(
             local_repos.clone(),
-            lib::generate_local_fonts(None).unwrap(),
+            lib::generate_local_fonts(None)?,
         ),
-        false => lib::init()?,
+        true => lib::init()?,
     };
 
-    match cli.command.as_str() {
-        "version" => {
-            print_version();
+    let installed_fonts = lib::InstalledFonts::load()?;
+
+    let mut output = Output::new(format);
+    let text = output.is_text();
+    let mut failed = false;
+
+    match command {
+        Command::Version | Command::Completions { .. } | Command::Man => {
+            output.record(json!({
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            }));
         }
-        "update-repos" => {
-            for r in local_repos_file.iter() {
-                println!("Updating {}...", r.name);
-                let mut file = File::create(repos_dir.join(r.name.clone() + ".json"))?;
-                file.write_all(
-                    serde_json::to_string_pretty(&json!({
-                        "kind": "webfonts#webfontList",
-                        "items": &lib::generate_repo_font_list_from_url(
-                            &r.url, r.key.clone()
-                        )?
-                    }))?
-                    .as_bytes(),
-                )?;
+        Command::UpdateRepos => {
+            let verifies = local_repos_file
+                .iter()
+                .any(|r| r.verify != lib::Verify::Off);
+            if let Some(keys_dir) = lib::trusted_keys_dir().filter(|_| verifies) {
+                for path in lib::invalid_trusted_keys(&keys_dir)? {
+                    eprintln!(
+                        "warning: {} is not an ed25519 public key, skipping",
+                        path.display()
+                    );
+                }
+            }
+            if text {
+                for r in local_repos_file.iter() {
+                    println!("Updating {}...", r.name);
+                }
+            }
+            let results = lib::refresh_repo_caches(&local_repos_file, &repos_dir, true);
+            for (r, result) in local_repos_file.iter().zip(results) {
+                let status = match result {
+                    Ok(lib::RefreshStatus::Updated) => "updated",
+                    Ok(lib::RefreshStatus::NotModified) => {
+                        if text {
+                            println!("{} is up to date", r.name);
+                        }
+                        "not-modified"
+                    }
+                    Ok(lib::RefreshStatus::Fresh) => "fresh",
+                    Err(_) => "failed",
+                };
+                let error = result.err().map(|e| report_error(&mut failed, text, &r.name, e));
+                output.record(json!({
+                    "repo": r.name,
+                    "status": status,
+                    "error": error,
+                }));
             }
         }
-        "list-local-repos" => {
-            for r in local_repos.keys() {
-                println!("{}", r);
+        Command::ListLocalRepos => {
+            let mut names: Vec<&String> = local_repos.keys().collect();
+            names.sort();
+            for r in names {
+                if text {
+                    println!("{}", r);
+                }
+                output.record(json!({
+                    "name": r,
+                    "fonts": local_repos[r].len(),
+                }));
             }
         }
-        "install" => {
-            for font in cli.fonts.iter() {
-                match fonts_list.get(font) {
-                    Some(data) => {
-                        data.clone().install_to_user(cli.repo.as_deref(), true)?;
+        Command::Install {
+            location,
+            repo,
+            dry_run,
+            fonts,
+        } => {
+            let location = location.get().unwrap_or(lib::Location::User);
+            let lock = lock_settings(cli.lockfile.as_deref(), cli.locked, &font_catcher_dir)?;
+            for font in fonts.iter() {
+                let (font, variants) = parse_font_spec(&fonts_list, font);
+                let record = action_record("install", font, Some(&location), repo.as_deref());
+                if dry_run {
+                    let plan = match (fonts_list.get(font), &variants) {
+                        (Some(data), Some(variants)) => data.plan_install_variants(
+                            repo.as_deref(),
+                            variants,
+                            &location,
+                            &installed_fonts,
+                            Some(&lock),
+                        ),
+                        (Some(data), None) => data.plan_install(
+                            repo.as_deref(),
+                            &location,
+                            &installed_fonts,
+                            Some(&lock),
+                        ),
+                        (None, _) => Err(not_found(font)),
+                    };
+                    output.record(finish_plan(record, plan, &mut failed, text));
+                    continue;
+                }
+                let result = match (fonts_list.get(font), &variants) {
+                    (Some(data), Some(variants)) => data.clone().install_variants(
+                        repo.as_deref(),
+                        variants,
+                        &location,
+                        Some(&lock),
+                        text,
+                    ),
+                    (Some(data), None) if location == lib::Location::System => {
+                        data.clone().install_to_system(repo.as_deref(), Some(&lock), text)
                     }
-                    None => {
-                        println!("{} not found anywhere!", font);
+                    (Some(data), None) => {
+                        data.clone().install_to_user(repo.as_deref(), Some(&lock), text)
                     }
+                    (None, _) => Err(not_found(font)),
                 };
+                output.record(finish_action(record, result, &mut failed, text));
             }
         }
-        "download" => {
-            for font in cli.fonts.iter() {
-                match fonts_list.get(font) {
-                    Some(data) => {
-                        data.clone().download(cli.repo.as_deref(), &cli.path, true)?;
-                    }
-                    None => {
-                        println!("{} not found anywhere!", font);
+        Command::Download {
+            repo,
+            path,
+            dry_run,
+            fonts,
+        } => {
+            let lock = lock_settings(cli.lockfile.as_deref(), cli.locked, &font_catcher_dir)?;
+            for font in fonts.iter() {
+                let (font, variants) = parse_font_spec(&fonts_list, font);
+                let record = action_record("download", font, None, repo.as_deref());
+                if dry_run {
+                    let plan = match (fonts_list.get(font), &variants) {
+                        (Some(data), Some(variants)) => data.plan_download_variants(
+                            repo.as_deref(),
+                            variants,
+                            &path,
+                            Some(&lock),
+                        ),
+                        (Some(data), None) => {
+                            data.plan_download(repo.as_deref(), &path, Some(&lock))
+                        }
+                        (None, _) => Err(not_found(font)),
+                    };
+                    output.record(finish_plan(record, plan, &mut failed, text));
+                    continue;
+                }
+                let result = match (fonts_list.get(font), &variants) {
+                    (Some(data), Some(variants)) => {
+                        data.download_variants(repo.as_deref(), variants, &path, Some(&lock), text)
                     }
+                    (Some(data), None) => data.download(repo.as_deref(), &path, Some(&lock), text),
+                    (None, _) => Err(not_found(font)),
                 };
+                output.record(finish_action(record, result, &mut failed, text));
             }
         }
-        "search" => {
-            for font in cli.fonts.iter() {
-                for (name, data) in &fonts_list {
+        Command::Search {
+            location,
+            filter,
+            queries,
+        } => {
+            let location = location.get().unwrap_or(lib::Location::User);
+            let filter = filter.filter();
+            for query in queries.iter() {
+                for (data, search_match) in fonts_list.search_ranked(query) {
                     let mut data = data.clone();
-                    if name.to_lowercase().contains(&font.to_lowercase())
-                        && (match cli.repo {
-                            Some(ref repo) => data.is_font_in_repo(&repo),
-                            None => true,
-                        })
-                    {
-                        println!("\n{}:", &name);
-                        println!(
-                            "  Available on: {}",
-                            match data.get_repos_availability() {
-                                Some(r) => r.join(" "),
-                                None => "".to_string(),
-                            }
-                        );
+                    if filter.matches(&mut data, &location, &installed_fonts) {
+                        if text {
+                            println!("\n{}:", data.get_family());
+                            println!(
+                                "  Available on: {}",
+                                match data.get_repos_availability() {
+                                    Some(r) => r.join(" "),
+                                    None => "".to_string(),
+                                }
+                            );
 
-                        println!("  User installed: {}", data.is_font_user_installed());
-                        println!("  System installed: {}", data.is_font_system_installed());
+                            println!("  User installed: {}", data.is_font_user_installed());
+                            println!("  System installed: {}", data.is_font_system_installed());
+                        }
+                        let mut record = font_record(&mut data);
+                        record["match"] = json!(search_match.kind);
+                        output.record(record);
                     }
                 }
             }
         }
-        "remove" => {
-            for font in cli.fonts.iter() {
-                match fonts_list.get(font) {
-                    Some(data) => {
-                        data.clone().uninstall_from_user(true)?;
+        Command::Remove {
+            location,
+            force,
+            dry_run,
+            fonts,
+        } => {
+            let location = location.get().unwrap_or(lib::Location::User);
+            for font in fonts.iter() {
+                let (font, variants) = parse_font_spec(&fonts_list, font);
+                let record = action_record("remove", font, Some(&location), None);
+                if dry_run {
+                    let plan = match (fonts_list.get(font), &variants) {
+                        (Some(data), Some(variants)) => data.clone().plan_uninstall_variants(
+                            &location,
+                            variants,
+                            force,
+                            &installed_fonts,
+                        ),
+                        (Some(data), None) => {
+                            data.clone().plan_uninstall(&location, force, &installed_fonts)
+                        }
+                        (None, _) => Err(not_found(font)),
+                    };
+                    let not_managed = matches!(plan, Err(Error::NotManaged(_)));
+                    if text && not_managed {
+                        println!(
+                            "{} was not installed by font-catcher, use --force to remove it anyway",
+                            font
+                        );
                     }
-                    None => {
-                        println!("{} not found anywhere!", font);
+                    output.record(finish_plan(record, plan, &mut failed, text && !not_managed));
+                    continue;
+                }
+                let result = match (fonts_list.get(font), &variants) {
+                    (Some(data), Some(variants)) => {
+                        data.clone().uninstall_variants(&location, variants, force, text)
                     }
+                    (Some(data), None) => data.clone().uninstall(&location, force, text),
+                    (None, _) => Err(not_found(font)),
                 };
+                let not_managed = matches!(result, Err(Error::NotManaged(_)));
+                if text && not_managed {
+                    println!(
+                        "{} was not installed by font-catcher, use --force to remove it anyway",
+                        font
+                    );
+                }
+                output.record(finish_action(record, result, &mut failed, text && !not_managed));
             }
         }
-        "check-for-updates" => {
-            for (name, data) in fonts_list {
+        Command::CheckForUpdates { location } => {
+            let location = location.get().unwrap_or(lib::Location::User);
+            for (data, repos) in fonts_list.with_updates(&location, &installed_fonts) {
                 let mut data = data.clone();
-                if cli.location == Some(lib::Location::System) {
-                    match data.get_all_repos_with_update_system() {
-                        Some(repos) => {
-                            println!("Updates for {} available on:", name);
-                            for r in repos.iter() {
-                                println!("  {}", r);
-                            }
+                let installed = data.get_local_version(&location, &installed_fonts);
+                if text {
+                    println!("Updates for {} available on:", data.get_family());
+                    for r in repos.iter() {
+                        println!(
+                            "  {}: {} → {}",
+                            r,
+                            installed.as_deref().unwrap_or("unknown"),
+                            data.get_repo_version(r).unwrap_or_else(|| "unknown".to_string())
+                        );
+                    }
+                }
+                let updates: Map<String, Value> = repos
+                    .iter()
+                    .map(|r| (r.clone(), json!(data.get_repo_version(r))))
+                    .collect();
+                output.record(json!({
+                    "family": data.get_family(),
+                    "location": location_name(&location),
+                    "installed": installed,
+                    "updates": updates,
+                }));
+            }
+        }
+        Command::UpdateAll {
+            location,
+            force,
+            dry_run,
+        } => {
+            let location = location.get().unwrap_or(lib::Location::User);
+            let lock = lock_settings(cli.lockfile.as_deref(), cli.locked, &font_catcher_dir)?;
+            for (data, repos) in fonts_list.with_updates(&location, &installed_fonts) {
+                if !force && !installed_fonts.is_installed(&data.get_family(), &location) {
+                    continue;
+                }
+                let record =
+                    action_record("update", &data.get_family(), Some(&location), Some(&repos[0]));
+                if dry_run {
+                    let plan =
+                        data.plan_update(Some(&repos[0]), &location, &installed_fonts, Some(&lock));
+                    output.record(finish_plan(record, plan, &mut failed, text));
+                    continue;
+                }
+                let result = data.clone().update(Some(&repos[0]), &location, Some(&lock), text);
+                output.record(finish_action(record, result, &mut failed, text));
+            }
+        }
+        Command::Update {
+            location,
+            force,
+            dry_run,
+            fonts,
+        } => {
+            let location = location.get().unwrap_or(lib::Location::User);
+            let lock = lock_settings(cli.lockfile.as_deref(), cli.locked, &font_catcher_dir)?;
+            for font in fonts.iter() {
+                let mut data = match fonts_list.get(font) {
+                    Some(_) if !force && !installed_fonts.is_installed(font, &location) => {
+                        if text {
+                            println!(
+                                "{} was not installed by font-catcher, use --force to update it anyway",
+                                font
+                            );
                         }
-                        None => {}
+                        Err(Error::NotManaged(font.clone()))
                     }
-                } else {
-                    match data.get_all_repos_with_update_user() {
-                        Some(repos) => {
-                            println!("Updates for {} available on:", name);
-                            for r in repos.iter() {
-                                println!("  {}", r);
-                            }
+                    Some(data) => Ok(data.clone()),
+                    None => Err(not_found(font)),
+                };
+                let repo = data
+                    .as_mut()
+                    .ok()
+                    .and_then(|data| data.get_all_repos_with_update(&location, &installed_fonts))
+                    .map(|repos| repos[0].clone());
+                let record = action_record("update", font, Some(&location), repo.as_deref());
+                let not_managed = matches!(data, Err(Error::NotManaged(_)));
+                if dry_run {
+                    let plan = data.and_then(|data| match &repo {
+                        Some(repo) => {
+                            data.plan_update(Some(repo), &location, &installed_fonts, Some(&lock))
                         }
-                        None => {}
+                        None => Ok(lib::Plan {
+                            family: data.get_family(),
+                            ..lib::Plan::default()
+                        }),
+                    });
+                    output.record(finish_plan(record, plan, &mut failed, text && !not_managed));
+                    continue;
+                }
+                let result = data.and_then(|mut data| match &repo {
+                    Some(repo) => data.update(Some(repo), &location, Some(&lock), text),
+                    None => Ok(Vec::new()),
+                });
+                output.record(finish_action(record, result, &mut failed, text && !not_managed));
+            }
+        }
+        Command::Sync { project, dry_run } => {
+            let project = project.load()?;
+            let lock = lib::LockSettings {
+                path: cli.lockfile.clone().unwrap_or_else(|| project.lock_path()),
+                locked: cli.locked,
+            };
+            let location = match project.target_dir() {
+                Some(_) => None,
+                None => Some(lib::Location::User),
+            };
+            if dry_run {
+                let plans = project.plan_sync(&fonts_list, &installed_fonts, &lock);
+                for (requirement, plan) in project.fonts.iter().zip(plans) {
+                    if text && plan.as_ref().is_ok_and(lib::Plan::is_empty) {
+                        println!("{} is up to date", requirement.family);
+                    }
+                    let record =
+                        action_record("sync", &requirement.family, location.as_ref(), None);
+                    output.record(finish_plan(record, plan, &mut failed, text));
+                }
+            } else {
+                let results = project.sync(&fonts_list, &installed_fonts, &lock, text);
+                for (requirement, result) in project.fonts.iter().zip(results) {
+                    if text && result.as_ref().is_ok_and(Vec::is_empty) {
+                        println!("{} is up to date", requirement.family);
                     }
+                    let record =
+                        action_record("sync", &requirement.family, location.as_ref(), None);
+                    output.record(finish_action(record, result, &mut failed, text));
                 }
             }
         }
-        "update-all" => {
-            for (_name, data) in fonts_list {
-                let mut data = data.clone();
-                if cli.location == Some(lib::Location::System) {
-                    match data.get_all_repos_with_update_system() {
-                        Some(repos) => {
-                            data.install_to_user(Some(&repos[0]), true)?;
-                        }
-                        None => {}
+        Command::Check { project } => {
+            let project = project.load()?;
+            let lock = lib::LockSettings {
+                path: cli.lockfile.clone().unwrap_or_else(|| project.lock_path()),
+                locked: cli.locked,
+            };
+            let checks = project.check(&fonts_list, &installed_fonts, &lock);
+            for (requirement, check) in project.fonts.iter().zip(checks) {
+                let family = &requirement.family;
+                let check = match check {
+                    Ok(check) => check,
+                    Err(e) => {
+                        let error = report_error(&mut failed, text, family, e);
+                        output.record(json!({
+                            "family": family,
+                            "status": "unavailable",
+                            "repo": null,
+                            "version": null,
+                            "missing": [],
+                            "error": error,
+                        }));
+                        continue;
                     }
-                } else {
-                    match data.get_all_repos_with_update_user() {
-                        Some(repos) => {
-                            data.install_to_user(Some(&repos[0]), true)?;
+                };
+                let (status, missing) = match &check.status {
+                    lib::Status::Satisfied => ("ok", Vec::new()),
+                    lib::Status::Missing(missing) => ("missing", missing.clone()),
+                    lib::Status::WrongVersion(_) => ("wrong-version", Vec::new()),
+                };
+                if check.status != lib::Status::Satisfied {
+                    failed = true;
+                }
+                if text {
+                    match &check.status {
+                        lib::Status::Satisfied => println!("{}: ok", family),
+                        lib::Status::Missing(missing) => {
+                            println!("{}: missing {}", family, missing.join(" "))
                         }
-                        None => {}
+                        lib::Status::WrongVersion(version) => println!(
+                            "{}: version {} doesn't match {}",
+                            family,
+                            version.as_deref().unwrap_or("unknown"),
+                            requirement.version.as_deref().unwrap_or_default()
+                        ),
                     }
                 }
+                output.record(json!({
+                    "family": family,
+                    "status": status,
+                    "repo": check.repo,
+                    "version": check.version,
+                    "missing": missing,
+                    "error": null,
+                }));
             }
         }
-        "update" => {
-            for font in cli.fonts.iter() {
-                match fonts_list.get(font) {
+        Command::Info { fonts } => {
+            for font in fonts.iter() {
+                match fonts_list.get_case_insensitive(font) {
                     Some(data) => {
                         let mut data = data.clone();
-                        if cli.location == Some(lib::Location::System)
-                            && data.is_update_available_system()
-                        {
-                            let repo_with_update = &data.get_all_repos_with_update_system().unwrap()[0];
-                            data.install_to_user(
-                                Some(repo_with_update),
-                                true,
-                            )?;
-                        } else if data.is_update_available_user() {
-                            let repo_with_update = &data.get_all_repos_with_update_user().unwrap()[0];
-                            data.install_to_user(
-                                Some(repo_with_update),
-                                true,
-                            )?;
+                        let record = info_record(&mut data, &installed_fonts);
+                        if text {
+                            print_info(&record);
                         }
+                        output.record(record);
                     }
                     None => {
-                        println!("{} not found anywhere!", font);
+                        let error = report_error(&mut failed, text, font, not_found(font));
+                        output.record(json!({ "family": font, "error": error }));
                     }
                 }
             }
         }
-        "list" => {
-            for (name, data) in fonts_list {
+        Command::List {
+            location,
+            force,
+            filter,
+        } => {
+            let filter = filter.filter();
+            let listed = match location.get() {
+                Some(ref location) if !force => fonts_list.managed(location, &installed_fonts),
+                Some(ref location) => fonts_list.installed(location),
+                None => fonts_list.iter().collect(),
+            };
+            let location = location.get().unwrap_or(lib::Location::User);
+            for data in listed {
                 let mut data = data.clone();
-                if (cli.repo != None && data.is_font_in_repo(&cli.repo.as_ref().unwrap()))
-                    || cli.repo == None
-                {
-                    if cli.location == Some(lib::Location::System)
-                        && data.is_font_system_installed()
-                    {
-                        println!("{}", name);
-                    } else if cli.location == Some(lib::Location::User)
-                        && data.is_font_user_installed()
-                    {
-                        println!("{}", name);
-                    } else if cli.location == None {
-                        println!("{}", name);
-                    }
+                if !filter.matches(&mut data, &location, &installed_fonts) {
+                    continue;
                 }
+                if text {
+                    println!("{}", data.get_family());
+                }
+                output.record(font_record(&mut data));
             }
         }
-        _ => {
-            println!("{} is not a valid operation, skipping...", cli.command);
-        }
     }
-    Ok(())
+
+    output.finish();
+    Ok(!failed)
 }
 
 fn main() {
+    CompleteEnv::with_factory(Cli::command).complete();
+
     let result = run();
     match result {
-        Ok(()) => {
+        Ok(true) => {
             exit(0);
         }
+        Ok(false) => {
+            exit(1);
+        }
         Err(err) => {
             eprintln!("error: {:#}", err);
             exit(1);
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{font_catcher_dir, write_atomic, Error, Location, Result};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InstalledFile {
+    pub variant: String,
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+/// What font-catcher wrote for one family in one location.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InstallRecord {
+    pub family: String,
+    pub location: Location,
+    pub repo: String,
+    pub version: Option<String>,
+    #[serde(rename = "lastModified")]
+    pub last_modified: Option<String>,
+    pub files: Vec<InstalledFile>,
+    /// Variants chosen with `Font::install_variants`, kept by updates. `None`
+    /// when every variant was installed.
+    #[serde(default)]
+    pub variants: Option<Vec<String>>,
+    /// RFC 3339 timestamp of the install.
+    #[serde(rename = "installedAt")]
+    pub installed_at: String,
+}
+
+/// The manifest of every font installed by font-catcher, stored as
+/// `installed.json` in the font-catcher data directory.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct InstalledFonts {
+    pub installs: Vec<InstallRecord>,
+}
+
+pub fn installed_fonts_path() -> Option<PathBuf> {
+    font_catcher_dir().map(|dir| dir.join("installed.json"))
+}
+
+impl InstalledFonts {
+    pub fn load() -> Result<Self> {
+        match installed_fonts_path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = installed_fonts_path()
+            .ok_or_else(|| Error::NotFound("data directory".to_string()))?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    pub fn get(&self, family: &str, location: &Location) -> Option<&InstallRecord> {
+        self.installs
+            .iter()
+            .find(|record| record.family == family && &record.location == location)
+    }
+
+    pub fn is_installed(&self, family: &str, location: &Location) -> bool {
+        self.get(family, location).is_some()
+    }
+
+    /// Adds `record`, replacing any previous record of the same family and location.
+    pub fn record(&mut self, record: InstallRecord) {
+        self.remove(&record.family.clone(), &record.location.clone());
+        self.installs.push(record);
+    }
+
+    pub fn remove(&mut self, family: &str, location: &Location) -> Option<InstallRecord> {
+        let index = self
+            .installs
+            .iter()
+            .position(|record| record.family == family && &record.location == location)?;
+        Some(self.installs.remove(index))
+    }
+
+    pub fn families(&self, location: &Location) -> Vec<String> {
+        self.installs
+            .iter()
+            .filter(|record| &record.location == location)
+            .map(|record| record.family.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,506 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+
+use curl::easy::{Easy, List};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+/// Request settings that can be given globally in `[network]` and for each
+/// repository, where they take precedence.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct HttpOptions {
+    /// Proxy URL, e.g. `http://proxy.example.com:3128`.
+    pub proxy: Option<String>,
+    /// PEM file with the certificate authorities to trust instead of the system ones.
+    pub ca_bundle: Option<PathBuf>,
+    /// Extra request headers. `${NAME}` in a value is replaced with the
+    /// environment variable `NAME`.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub user_agent: Option<String>,
+}
+
+impl HttpOptions {
+    /// `self` with the unset settings taken from `defaults`. Headers of both
+    /// are sent, the ones in `self` winning on conflicts.
+    pub fn or(&self, defaults: &HttpOptions) -> HttpOptions {
+        let mut headers = defaults.headers.clone();
+        headers.extend(self.headers.clone());
+        HttpOptions {
+            proxy: self.proxy.clone().or_else(|| defaults.proxy.clone()),
+            ca_bundle: self.ca_bundle.clone().or_else(|| defaults.ca_bundle.clone()),
+            headers,
+            user_agent: self.user_agent.clone().or_else(|| defaults.user_agent.clone()),
+        }
+    }
+}
+
+/// The `[network]` section of `repos.conf`. It applies to catalog fetches
+/// and font downloads alike.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Seconds allowed to establish a connection.
+    pub connect_timeout: u64,
+    /// Seconds allowed for a whole transfer, `0` means no limit.
+    pub timeout: u64,
+    /// How many times a failed transfer is retried.
+    pub retries: u32,
+    /// Milliseconds to wait before the first retry, doubled on every retry.
+    pub retry_delay: u64,
+    /// Continue interrupted font downloads with HTTP Range requests.
+    pub resume: bool,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            connect_timeout: 30,
+            timeout: 300,
+            retries: 3,
+            retry_delay: 1000,
+            resume: true,
+            http: HttpOptions::default(),
+        }
+    }
+}
+
+/// Headers identifying a version of a response, sent back in conditional
+/// requests so an unchanged resource costs a `304 Not Modified`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    #[serde(rename = "lastModified")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn request_headers(&self) -> Vec<String> {
+        let mut headers = Vec::new();
+        if let Some(etag) = &self.etag {
+            headers.push(format!("If-None-Match: {}", etag));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            headers.push(format!("If-Modified-Since: {}", last_modified));
+        }
+        headers
+    }
+
+    /// Value for an `If-Range` header: a strong ETag, or else the
+    /// Last-Modified date.
+    fn if_range(&self) -> Option<&str> {
+        match &self.etag {
+            Some(etag) if !etag.starts_with("W/") => Some(etag),
+            _ => self.last_modified.as_deref(),
+        }
+    }
+
+    /// Whether a response with `other` validators can be the same version of
+    /// the resource. Validators missing on either side don't tell.
+    fn matches(&self, other: &Validators) -> bool {
+        match (self, other) {
+            (Validators { etag: Some(a), .. }, Validators { etag: Some(b), .. }) => a == b,
+            (
+                Validators { last_modified: Some(a), .. },
+                Validators { last_modified: Some(b), .. },
+            ) => a == b,
+            _ => true,
+        }
+    }
+
+    /// Records `header` if it is an `ETag` or `Last-Modified` response header.
+    fn read_header(&mut self, header: &[u8]) {
+        let line = match std::str::from_utf8(header) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if let Some((name, value)) = line.split_once(':') {
+            let value = Some(value.trim().to_string());
+            if name.eq_ignore_ascii_case("etag") {
+                self.etag = value;
+            } else if name.eq_ignore_ascii_case("last-modified") {
+                self.last_modified = value;
+            }
+        }
+    }
+}
+
+static NETWORK_CONFIG: RwLock<Option<NetworkConfig>> = RwLock::new(None);
+
+/// Replaces the network settings used by every following transfer.
+pub fn set_network_config(config: NetworkConfig) {
+    *NETWORK_CONFIG.write().unwrap() = Some(config);
+}
+
+pub fn network_config() -> NetworkConfig {
+    NETWORK_CONFIG.read().unwrap().clone().unwrap_or_default()
+}
+
+/// Replaces every `${NAME}` in `value` with the environment variable `NAME`.
+fn expand_env(value: &str) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| Error::Parse(format!("unclosed ${{ in header value {}", value)))?;
+        let name = &rest[start + 2..start + end];
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(
+            &env::var(name)
+                .map_err(|_| Error::NotFound(format!("environment variable {}", name)))?,
+        );
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn apply_options(handle: &mut Easy, options: &HttpOptions, extra_headers: &[String]) -> Result<()> {
+    handle.useragent(options.user_agent.as_deref().unwrap_or(concat!(
+        env!("CARGO_PKG_NAME"),
+        "/",
+        env!("CARGO_PKG_VERSION")
+    )))?;
+    if let Some(proxy) = &options.proxy {
+        handle.proxy(proxy)?;
+    }
+    if let Some(ca_bundle) = &options.ca_bundle {
+        handle.cainfo(ca_bundle)?;
+    }
+    if !options.headers.is_empty() || !extra_headers.is_empty() {
+        let mut headers = List::new();
+        for (name, value) in options.headers.iter() {
+            headers.append(&format!("{}: {}", name, expand_env(value)?))?;
+        }
+        for header in extra_headers.iter() {
+            headers.append(header)?;
+        }
+        handle.http_headers(headers)?;
+    }
+    Ok(())
+}
+
+/// Status code of a `HTTP/1.1 206 Partial Content` header line.
+fn parse_status_line(header: &[u8]) -> Option<u32> {
+    let line = std::str::from_utf8(header).ok()?;
+    if !line.starts_with("HTTP/") {
+        return None;
+    }
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Performs one request for `url`, with `resume` starting at a byte offset if
+/// the resource still matches the given `If-Range` value. `write` is called
+/// with the body, and with the validators of the response when the body
+/// starts over from the beginning, which happens when the server ignores the
+/// range or the resource changed. Error bodies are discarded and the final
+/// status code is returned with the validators of the response. With
+/// `cached` validators the request is conditional.
+fn perform<F>(
+    url: &str,
+    config: &NetworkConfig,
+    options: &HttpOptions,
+    cached: &Validators,
+    resume: Option<(u64, &str)>,
+    mut write: F,
+) -> Result<(u32, Validators)>
+where
+    F: FnMut(&[u8], Option<&Validators>) -> io::Result<()>,
+{
+    let mut handle = Easy::new();
+    handle.url(url)?;
+    let mut headers = cached.request_headers();
+    if let Some((_, if_range)) = resume {
+        headers.push(format!("If-Range: {}", if_range));
+    }
+    apply_options(&mut handle, options, &headers)?;
+    handle.follow_location(true)?;
+    handle.connect_timeout(Duration::from_secs(config.connect_timeout))?;
+    if config.timeout > 0 {
+        handle.timeout(Duration::from_secs(config.timeout))?;
+    }
+    if let Some((offset, _)) = resume {
+        handle.range(&format!("{}-", offset))?;
+    }
+
+    let status = Cell::new(0);
+    let validators = RefCell::new(Validators::default());
+    let mut started = false;
+    let mut write_error = None;
+    let result = {
+        let mut transfer = handle.transfer();
+        transfer.header_function(|header| {
+            match parse_status_line(header) {
+                // Headers of a redirect start over
+                Some(code) => {
+                    status.set(code);
+                    *validators.borrow_mut() = Validators::default();
+                }
+                None => validators.borrow_mut().read_header(header),
+            }
+            true
+        })?;
+        transfer.write_function(|data| {
+            if status.get() >= 400 {
+                return Ok(data.len());
+            }
+            let restart = !started && status.get() != 206;
+            started = true;
+            let validators = validators.borrow();
+            match write(data, Some(&*validators).filter(|_| restart)) {
+                Ok(()) => Ok(data.len()),
+                Err(e) => {
+                    write_error = Some(e);
+                    Ok(0)
+                }
+            }
+        })?;
+        transfer.perform()
+    };
+    if let Some(e) = write_error {
+        return Err(Error::Io(e));
+    }
+    result?;
+    Ok((handle.response_code()?, validators.into_inner()))
+}
+
+/// Whether trying again may help: connection problems, timeouts and
+/// server-side HTTP errors.
+fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::Network(e) => !(e.is_url_malformed() || e.is_unsupported_protocol()),
+        Error::Http { status, .. } => *status == 408 || *status == 429 || *status >= 500,
+        _ => false,
+    }
+}
+
+/// How long to wait before each retry: `retry_delay`, doubled every time.
+fn retry_delays(config: &NetworkConfig) -> impl Iterator<Item = Duration> {
+    let delay = Duration::from_millis(config.retry_delay);
+    (0..config.retries).map(move |retry| delay.saturating_mul(2u32.saturating_pow(retry)))
+}
+
+fn with_retries<T, F>(config: &NetworkConfig, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+{
+    let mut delays = retry_delays(config);
+    loop {
+        match attempt() {
+            Err(e) if is_transient(&e) => match delays.next() {
+                Some(delay) => thread::sleep(delay),
+                None => return Err(e),
+            },
+            result => return result,
+        }
+    }
+}
+
+fn check_status(url: &str, status: u32) -> Result<()> {
+    if status >= 400 {
+        return Err(Error::Http {
+            url: url.to_string(),
+            status,
+        });
+    }
+    Ok(())
+}
+
+/// Downloads `url` into memory. `options` are completed with the ones of the
+/// `[network]` section.
+pub(crate) fn download(url: &str, options: &HttpOptions) -> Result<Vec<u8>> {
+    download_if_changed(url, options, &Validators::default())
+        .map(|response| response.map(|(data, _validators)| data).unwrap_or_default())
+}
+
+/// Like `download`, but returns `None` when the server answers that the
+/// resource hasn't changed since the response `cached` came from.
+pub(crate) fn download_if_changed(
+    url: &str,
+    options: &HttpOptions,
+    cached: &Validators,
+) -> Result<Option<(Vec<u8>, Validators)>> {
+    let config = network_config();
+    let options = options.or(&config.http);
+    with_retries(&config, || {
+        let mut data = Vec::new();
+        let (status, validators) = perform(url, &config, &options, cached, None, |chunk, _| {
+            data.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        check_status(url, status)?;
+        if status == 304 {
+            return Ok(None);
+        }
+        Ok(Some((data, validators)))
+    })
+}
+
+/// Where the validators of the response a partial download came from are
+/// kept, next to the partial file.
+pub(crate) fn validators_path(partial_file: &Path) -> PathBuf {
+    let mut path = partial_file.as_os_str().to_owned();
+    path.push(".json");
+    PathBuf::from(path)
+}
+
+/// Offset and `If-Range` value to continue a partial download of `offset`
+/// bytes from, if resuming is `enabled`. Partial files from a response that
+/// can't be identified start over.
+fn resume_point(stored: Option<&Validators>, offset: u64, enabled: bool) -> Option<(u64, String)> {
+    if !enabled || offset == 0 {
+        return None;
+    }
+    stored?.if_range().map(|if_range| (offset, if_range.to_string()))
+}
+
+/// Whether a download has to be requested again from the start after a
+/// response with `status` and `received` validators: the partial file is
+/// already complete, or a server ignoring If-Range sent the rest of a newer
+/// version.
+fn must_restart(status: u32, resumed: bool, stored: Option<&Validators>, received: &Validators) -> bool {
+    match status {
+        206 => stored.is_some_and(|stored| !stored.matches(received)),
+        416 => resumed,
+        _ => false,
+    }
+}
+
+/// Downloads `url` into `partial_file`. When resuming is enabled and the file
+/// already holds the start of the download, only the rest is requested, and
+/// only if the resource hasn't changed since: otherwise it starts over.
+pub(crate) fn download_to_file(url: &str, partial_file: &Path, options: &HttpOptions) -> Result<()> {
+    let config = network_config();
+    let options = options.or(&config.http);
+    let validators_file = validators_path(partial_file);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(partial_file)
+        .map_err(|e| Error::with_path(e, partial_file.to_path_buf()))?;
+
+    with_retries(&config, || {
+        let stored: Option<Validators> = fs::read(&validators_file)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok());
+        let offset = fs::metadata(partial_file)?.len();
+        let resume = resume_point(stored.as_ref(), offset, config.resume);
+        let mut write = |chunk: &[u8], restart: Option<&Validators>| {
+            if let Some(validators) = restart {
+                file.set_len(0)?;
+                fs::write(&validators_file, serde_json::to_vec(validators)?)?;
+            }
+            file.write_all(chunk)
+        };
+        let resume_from = resume
+            .as_ref()
+            .map(|(offset, if_range)| (*offset, if_range.as_str()));
+        let no_validators = Validators::default();
+        let (mut status, validators) =
+            perform(url, &config, &options, &no_validators, resume_from, &mut write)?;
+        if must_restart(status, resume.is_some(), stored.as_ref(), &validators) {
+            status = perform(url, &config, &options, &no_validators, None, &mut write)?.0;
+        }
+        check_status(url, status)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validators(etag: Option<&str>, last_modified: Option<&str>) -> Validators {
+        Validators {
+            etag: etag.map(str::to_string),
+            last_modified: last_modified.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn resumes_only_identified_partial_files() {
+        let strong = validators(Some("\"v1\""), None);
+        assert_eq!(resume_point(Some(&strong), 10, true), Some((10, "\"v1\"".to_string())));
+        assert_eq!(resume_point(Some(&strong), 0, true), None);
+        assert_eq!(resume_point(Some(&strong), 10, false), None);
+        assert_eq!(resume_point(None, 10, true), None);
+
+        let weak = validators(Some("W/\"v1\""), Some("Wed, 01 Jan 2025 00:00:00 GMT"));
+        assert_eq!(
+            resume_point(Some(&weak), 10, true),
+            Some((10, "Wed, 01 Jan 2025 00:00:00 GMT".to_string()))
+        );
+        assert_eq!(resume_point(Some(&validators(Some("W/\"v1\""), None)), 10, true), None);
+    }
+
+    #[test]
+    fn restarts_on_changed_or_complete_partial_files() {
+        let v1 = validators(Some("\"v1\""), None);
+        let v2 = validators(Some("\"v2\""), None);
+        assert!(!must_restart(206, true, Some(&v1), &v1));
+        assert!(must_restart(206, true, Some(&v1), &v2));
+        assert!(must_restart(416, true, Some(&v1), &Validators::default()));
+        assert!(!must_restart(416, false, None, &Validators::default()));
+        assert!(!must_restart(200, true, Some(&v1), &v2));
+        assert!(!must_restart(404, true, Some(&v1), &v2));
+    }
+
+    #[test]
+    fn classifies_statuses() {
+        for status in [200, 206, 304] {
+            assert!(check_status("http://example.com", status).is_ok());
+        }
+        for (status, transient) in [(404, false), (416, false), (429, true), (503, true)] {
+            let error = check_status("http://example.com", status).unwrap_err();
+            assert!(matches!(error, Error::Http { status: s, .. } if s == status));
+            assert_eq!(is_transient(&error), transient);
+        }
+    }
+
+    #[test]
+    fn retry_delay_doubles() {
+        let config = NetworkConfig {
+            retries: 4,
+            retry_delay: 250,
+            ..NetworkConfig::default()
+        };
+        let delays: Vec<u128> = retry_delays(&config).map(|delay| delay.as_millis()).collect();
+        assert_eq!(delays, vec![250, 500, 1000, 2000]);
+
+        let config = NetworkConfig {
+            retries: 0,
+            ..NetworkConfig::default()
+        };
+        assert_eq!(retry_delays(&config).count(), 0);
+    }
+
+    #[test]
+    fn retries_only_transient_errors() {
+        let config = NetworkConfig {
+            retries: 2,
+            retry_delay: 0,
+            ..NetworkConfig::default()
+        };
+        let attempt = |status: u32| {
+            let mut attempts = 0;
+            let result: Result<()> = with_retries(&config, || {
+                attempts += 1;
+                check_status("http://example.com", status)
+            });
+            assert!(result.is_err());
+            attempts
+        };
+        assert_eq!(attempt(503), 3);
+        assert_eq!(attempt(404), 1);
+    }
+}
@@ -0,0 +1,131 @@
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Tsv,
+}
+
+/// Collects the records produced by a command and prints them once it is
+/// done. In text mode nothing is collected, commands print as they go.
+pub struct Output {
+    format: Format,
+    records: Vec<Map<String, Value>>,
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !(value.is_array() || value.is_object())
+}
+
+/// Lists of scalars are joined with commas and maps of scalars written as
+/// `key=value` pairs. Anything nested deeper is written as compact JSON.
+fn tsv_cell(value: &Value) -> String {
+    let cell = match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(items) if items.iter().all(is_scalar) => {
+            items.iter().map(tsv_cell).collect::<Vec<String>>().join(",")
+        }
+        Value::Object(fields) if fields.values().all(is_scalar) => fields
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, tsv_cell(value)))
+            .collect::<Vec<String>>()
+            .join(","),
+        other => other.to_string(),
+    };
+    cell.replace(['\t', '\n', '\r'], " ")
+}
+
+impl Output {
+    pub fn new(format: Format) -> Self {
+        Output {
+            format,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.format == Format::Text
+    }
+
+    /// Adds a record, a JSON object, to the output.
+    pub fn record(&mut self, record: Value) {
+        if let Value::Object(fields) = record {
+            if !self.is_text() {
+                self.records.push(fields);
+            }
+        }
+    }
+
+    /// Prints the collected records: a JSON array, or tab separated values
+    /// with a header line.
+    pub fn finish(&self) {
+        match self.format {
+            Format::Text => {}
+            Format::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&self.records).unwrap_or_default()
+                );
+            }
+            Format::Tsv => print!("{}", self.tsv()),
+        }
+    }
+
+    /// Tab separated values with a header line. Records of one command may
+    /// have different fields, so the columns are every field of any record,
+    /// in the order they first appear, and missing fields are left empty.
+    fn tsv(&self) -> String {
+        let mut columns: Vec<&String> = Vec::new();
+        for record in self.records.iter() {
+            for key in record.keys() {
+                if !columns.contains(&key) {
+                    columns.push(key);
+                }
+            }
+        }
+        if columns.is_empty() {
+            return String::new();
+        }
+        let mut lines = vec![columns
+            .iter()
+            .map(|column| column.as_str())
+            .collect::<Vec<&str>>()
+            .join("\t")];
+        for record in self.records.iter() {
+            lines.push(
+                columns
+                    .iter()
+                    .map(|column| record.get(*column).map(tsv_cell).unwrap_or_default())
+                    .collect::<Vec<String>>()
+                    .join("\t"),
+            );
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn tsv_aligns_records_with_different_fields() {
+        let mut output = Output::new(Format::Tsv);
+        output.record(json!({"family": "DejaVu Sans Mono", "repos": ["Arch", "NFS"]}));
+        output.record(json!({"family": "nope", "error": "nope not found"}));
+        assert_eq!(
+            output.tsv(),
+            "family\trepos\terror\nDejaVu Sans Mono\tArch,NFS\t\nnope\t\tnope not found\n"
+        );
+    }
+
+    #[test]
+    fn tsv_is_empty_without_records() {
+        assert_eq!(Output::new(Format::Tsv).tsv(), "");
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::Location;
+
+/// A file an install or download would fetch.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlannedDownload {
+    pub url: String,
+    /// Variants taken from the file, archives may hold several.
+    pub variants: Vec<String>,
+    /// Whether the variants are extracted from a .zip/.tar.gz archive.
+    pub archive: bool,
+}
+
+/// What installing, downloading or removing a font would do, worked out
+/// from the catalogs and the install manifest without fetching or changing
+/// anything. See `Font::plan_install`, `Font::plan_download` and
+/// `Font::plan_uninstall`.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Plan {
+    pub family: String,
+    pub repo: Option<String>,
+    pub location: Option<Location>,
+    pub downloads: Vec<PlannedDownload>,
+    /// Paths that would be written, replacing any file already there. For
+    /// variants inside archives the extension is a guess, see
+    /// `Font::output_paths`.
+    pub writes: Vec<PathBuf>,
+    /// Paths that would be deleted.
+    pub removals: Vec<PathBuf>,
+}
+
+impl Plan {
+    /// Whether carrying out the plan would change nothing.
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty() && self.writes.is_empty() && self.removals.is_empty()
+    }
+}
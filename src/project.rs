@@ -0,0 +1,391 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    version_matches, Catalog, Error, Font, InstalledFonts, Location, LockSettings,
+    Lockfile, Plan, Result, VariantFiles, LOCK_FILE,
+};
+
+pub const PROJECT_FILE: &str = "fonts.toml";
+
+/// A font a project needs, one `[[font]]` table of `fonts.toml`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FontRequirement {
+    pub family: String,
+    /// Variants needed, every variant of the repository when unset.
+    #[serde(default)]
+    pub variants: Option<Vec<String>>,
+    /// Repository to install from, the first one by name offering a matching
+    /// version when unset.
+    #[serde(default)]
+    pub repo: Option<String>,
+    /// Version constraint, e.g. `>= v30` (see `version_matches`).
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+/// The fonts a project needs, read from a `fonts.toml` file:
+///
+/// ```toml
+/// path = "fonts"
+///
+/// [[font]]
+/// family = "Roboto"
+/// variants = ["regular", "700"]
+/// repo = "Google Fonts"
+/// version = ">= v30"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Project {
+    /// Folder the fonts go into, relative to `fonts.toml`. Fonts are
+    /// installed for the current user when unset.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default, rename = "font")]
+    pub fonts: Vec<FontRequirement>,
+    /// Folder holding `fonts.toml`.
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+/// How the machine compares to a `FontRequirement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// Every variant is there, with a matching version.
+    Satisfied,
+    /// These variants aren't there.
+    Missing(Vec<String>),
+    /// Every variant is there, but the version found, if any, doesn't match.
+    WrongVersion(Option<String>),
+}
+
+/// Result of checking one `FontRequirement`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub family: String,
+    /// Repository `sync` installs from.
+    pub repo: String,
+    /// Version found on the machine.
+    pub version: Option<String>,
+    pub status: Status,
+}
+
+impl Project {
+    pub fn from_str(contents: &str, root: &Path) -> Result<Self> {
+        let mut project: Project = toml::from_str(contents)?;
+        project.root = root.to_path_buf();
+        Ok(project)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| Error::with_path(e, path.to_path_buf()))?;
+        let root = path.parent().unwrap_or_else(|| Path::new("."));
+        Self::from_str(&contents, root)
+    }
+
+    /// Looks for `fonts.toml` in `dir` and its parents.
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|dir| dir.join(PROJECT_FILE))
+            .find(|path| path.is_file())
+    }
+
+    /// Folder the fonts go into, `None` when they are installed for the user.
+    pub fn target_dir(&self) -> Option<PathBuf> {
+        self.path.as_ref().map(|path| self.root.join(path))
+    }
+
+    /// The project's lockfile, `fonts.lock` next to `fonts.toml`.
+    pub fn lock_path(&self) -> PathBuf {
+        self.root.join(LOCK_FILE)
+    }
+
+    /// Repository to install `requirement` from: the one it names, or the
+    /// first one by name offering a version that meets the constraint.
+    fn resolve_repo(&self, font: &Font, requirement: &FontRequirement) -> Result<String> {
+        let matches = |repo: &str| match &requirement.version {
+            Some(constraint) => font
+                .get_repo_version(repo)
+                .is_some_and(|version| version_matches(&version, constraint)),
+            None => true,
+        };
+        let constraint = requirement.version.as_deref().unwrap_or("");
+        match &requirement.repo {
+            Some(repo) if !font.is_font_in_repo(repo) => Err(Error::NotFound(format!(
+                "{} in {}",
+                requirement.family, repo
+            ))),
+            Some(repo) if !matches(repo) => Err(Error::NotFound(format!(
+                "{} {} in {}",
+                requirement.family, constraint, repo
+            ))),
+            Some(repo) => Ok(repo.clone()),
+            None => {
+                let mut repos = font.get_repos_availability().unwrap_or_default();
+                repos.sort();
+                repos.into_iter().find(|repo| matches(repo)).ok_or_else(|| {
+                    Error::NotFound(format!(
+                        "{} {} in any repository",
+                        requirement.family, constraint
+                    ))
+                })
+            }
+        }
+    }
+
+    /// Files of `font` found on the machine, keyed by variant, and their
+    /// version. For `target_dir` that is the repository version recorded in
+    /// the lockfile of `lock` when they were synced.
+    fn installed(
+        &self,
+        font: &mut Font,
+        repo: &str,
+        variants: &[String],
+        installed_fonts: &InstalledFonts,
+        lock: &LockSettings,
+    ) -> Result<(VariantFiles, Option<String>)> {
+        let dir = match self.target_dir() {
+            Some(dir) => dir,
+            None => {
+                let files = font.get_local_variant_files(&Location::User, installed_fonts);
+                let version = font.get_local_version(&Location::User, installed_fonts);
+                return Ok((files, version));
+            }
+        };
+        // Files a previous sync wrote, named like `download` names them
+        let mut files = VariantFiles::new();
+        for variant in variants {
+            let path = font
+                .plan_fetch(Some(repo), Some(std::slice::from_ref(variant)), &dir, Some(lock))
+                .ok()
+                .and_then(|plan| plan.writes.into_iter().next())
+                .filter(|path| path.is_file());
+            if let Some(path) = path {
+                files.insert(variant.clone(), vec![path]);
+            }
+        }
+        let lockfile = Lockfile::load(&lock.path)?;
+        let version = lockfile
+            .get(&font.family)
+            .filter(|locked| locked.repo == repo)
+            .filter(|locked| {
+                files
+                    .keys()
+                    .all(|variant| locked.variants.iter().any(|locked| &locked.variant == variant))
+            })
+            .and_then(|locked| locked.version.clone());
+        Ok((files, version))
+    }
+
+    /// Checks `requirement`, returning the font as well.
+    fn check_font(
+        &self,
+        catalog: &Catalog,
+        requirement: &FontRequirement,
+        installed_fonts: &InstalledFonts,
+        lock: &LockSettings,
+    ) -> Result<(Check, Font)> {
+        let mut font = catalog
+            .get(&requirement.family)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(requirement.family.clone()))?;
+        let repo = self.resolve_repo(&font, requirement)?;
+        let mut variants = match &requirement.variants {
+            Some(variants) => variants.clone(),
+            None => font.get_repo_variants(&repo).unwrap_or_default(),
+        };
+        variants.sort();
+
+        let (files, version) = self.installed(&mut font, &repo, &variants, installed_fonts, lock)?;
+        let missing: Vec<String> = variants
+            .into_iter()
+            .filter(|variant| !files.contains_key(variant))
+            .collect();
+        let version_ok = match &requirement.version {
+            Some(constraint) => version
+                .as_deref()
+                .is_some_and(|version| version_matches(version, constraint)),
+            None => true,
+        };
+        let status = if !missing.is_empty() {
+            Status::Missing(missing)
+        } else if !version_ok {
+            Status::WrongVersion(version.clone())
+        } else {
+            Status::Satisfied
+        };
+        let check = Check {
+            family: requirement.family.clone(),
+            repo,
+            version,
+            status,
+        };
+        Ok((check, font))
+    }
+
+    /// Compares the machine with every font of the project, in order. Fonts
+    /// synced into `target_dir` get their version from the lockfile of
+    /// `lock`, usually the project's (see `lock_path`).
+    pub fn check(
+        &self,
+        catalog: &Catalog,
+        installed_fonts: &InstalledFonts,
+        lock: &LockSettings,
+    ) -> Vec<Result<Check>> {
+        self.fonts
+            .iter()
+            .map(|requirement| {
+                self.check_font(catalog, requirement, installed_fonts, lock)
+                    .map(|(check, _font)| check)
+            })
+            .collect()
+    }
+
+    /// What `sync` would do for every font of the project, in order. Fonts
+    /// already satisfied get an empty plan.
+    pub fn plan_sync(
+        &self,
+        catalog: &Catalog,
+        installed_fonts: &InstalledFonts,
+        lock: &LockSettings,
+    ) -> Vec<Result<Plan>> {
+        self.fonts
+            .iter()
+            .map(|requirement| {
+                let (check, font) =
+                    self.check_font(catalog, requirement, installed_fonts, lock)?;
+                if check.status == Status::Satisfied {
+                    return Ok(Plan {
+                        family: check.family,
+                        ..Plan::default()
+                    });
+                }
+                let repo = Some(check.repo.as_str());
+                if let Some(dir) = self.target_dir() {
+                    let selection = requirement.variants.as_deref();
+                    return font.plan_fetch(repo, selection, &dir, Some(lock));
+                }
+                let variants = requirement
+                    .variants
+                    .as_ref()
+                    .map(|variants| variants.iter().map(String::as_str).collect::<Vec<&str>>());
+                match variants {
+                    Some(variants) => font.plan_install_variants(
+                        repo,
+                        &variants,
+                        &Location::User,
+                        installed_fonts,
+                        Some(lock),
+                    ),
+                    None => font.plan_install(repo, &Location::User, installed_fonts, Some(lock)),
+                }
+            })
+            .collect()
+    }
+
+    /// Installs the fonts of the project that are missing or don't match
+    /// their version constraint, into `target_dir` or for the current user,
+    /// locking them in the lockfile of `lock`. Returns the files written for
+    /// every font, in order.
+    pub fn sync(
+        &self,
+        catalog: &Catalog,
+        installed_fonts: &InstalledFonts,
+        lock: &LockSettings,
+        output: bool,
+    ) -> Vec<Result<Vec<PathBuf>>> {
+        self.fonts
+            .iter()
+            .map(|requirement| {
+                let (check, mut font) =
+                    self.check_font(catalog, requirement, installed_fonts, lock)?;
+                if check.status == Status::Satisfied {
+                    return Ok(Vec::new());
+                }
+                let repo = Some(check.repo.as_str());
+                if let Some(dir) = self.target_dir() {
+                    let selection = requirement.variants.as_deref();
+                    let fetched = font.fetch_variants(
+                        repo,
+                        selection,
+                        &dir,
+                        Some(lock),
+                        output,
+                        |_fetched| Ok(()),
+                    )?;
+                    return Ok(fetched.into_iter().map(|(_variant, path)| path).collect());
+                }
+                let variants = requirement
+                    .variants
+                    .as_ref()
+                    .map(|variants| variants.iter().map(String::as_str).collect::<Vec<&str>>());
+                match variants {
+                    Some(variants) => {
+                        font.install_variants(repo, &variants, &Location::User, Some(lock), output)
+                    }
+                    None => font.install_to_user(repo, Some(lock), output),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::system_font_file;
+    use crate::{generate_fonts_list, RepoFont};
+
+    fn catalog(font_file: &Path) -> Catalog {
+        let repo_font = RepoFont {
+            family: Some("Test Sans".to_string()),
+            variants: vec!["regular".to_string()],
+            version: Some("v10".to_string()),
+            files: vec![(
+                "regular".to_string(),
+                format!("file://{}", font_file.display()),
+            )]
+            .into_iter()
+            .collect(),
+            ..RepoFont::default()
+        };
+        let repos = vec![("Test".to_string(), vec![repo_font])].into_iter().collect();
+        generate_fonts_list(repos, Vec::new())
+    }
+
+    #[test]
+    fn sync_into_target_dir_satisfies_check() {
+        let font_file = match system_font_file() {
+            Some(font_file) => font_file,
+            None => return,
+        };
+        let catalog = catalog(&font_file);
+        let installed_fonts = InstalledFonts::default();
+        let root = tempfile::tempdir().unwrap();
+        let project = Project::from_str(
+            "path = \"fonts\"\n\n[[font]]\nfamily = \"Test Sans\"\nversion = \">= v10\"\n",
+            root.path(),
+        )
+        .unwrap();
+        let lock = LockSettings {
+            path: project.lock_path(),
+            locked: false,
+        };
+
+        let check = project.check(&catalog, &installed_fonts, &lock).remove(0).unwrap();
+        assert_eq!(check.status, Status::Missing(vec!["regular".to_string()]));
+
+        let written = project.sync(&catalog, &installed_fonts, &lock, false).remove(0).unwrap();
+        assert_eq!(written.len(), 1);
+        assert!(written[0].starts_with(root.path().join("fonts")));
+
+        let check = project.check(&catalog, &installed_fonts, &lock).remove(0).unwrap();
+        assert_eq!(check.status, Status::Satisfied);
+        assert_eq!(check.version.as_deref(), Some("v10"));
+        let plan = project.plan_sync(&catalog, &installed_fonts, &lock).remove(0).unwrap();
+        assert!(plan.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use serde::Serialize;
+
+use crate::Font;
+
+/// How a font matched a search, from the best to the weakest kind of match.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchKind {
+    /// The family is the query, ignoring case.
+    Exact,
+    /// The family starts with the query.
+    Prefix,
+    /// The family contains the query.
+    Substring,
+    /// The family is a few typos away from the query, e.g. `robto` -> `Roboto`.
+    Fuzzy,
+    /// The query appears in the creator, commentary or subsets of the font.
+    Metadata,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub kind: MatchKind,
+    /// Orders matches of the same kind, lower is better: the length of the
+    /// family for prefixes, the position of the query for substrings and the
+    /// edit distance for fuzzy matches.
+    pub score: usize,
+}
+
+/// Number of single character insertions, deletions and substitutions
+/// needed to turn `a` into `b`.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Typos tolerated in a query: one for short queries, a third of the length
+/// for longer ones.
+fn max_distance(query: &str) -> usize {
+    (query.chars().count() / 3).max(1)
+}
+
+/// Ranks how well `font` matches `query`, or `None` if it doesn't match at
+/// all. The family name is compared first, then the creator, commentary and
+/// subsets of every repository offering the font.
+pub fn rank_font(font: &Font, query: &str) -> Option<SearchMatch> {
+    let query = query.trim().to_lowercase();
+    let family = font.family.to_lowercase();
+    if query.is_empty() {
+        return None;
+    }
+
+    if family == query {
+        return Some(SearchMatch { kind: MatchKind::Exact, score: 0 });
+    }
+    if family.starts_with(&query) {
+        return Some(SearchMatch { kind: MatchKind::Prefix, score: family.len() });
+    }
+    if let Some(position) = family.find(&query) {
+        return Some(SearchMatch { kind: MatchKind::Substring, score: position });
+    }
+
+    let distance = family
+        .split_whitespace()
+        .map(|word| levenshtein(&query, word))
+        .chain(std::iter::once(levenshtein(&query, &family)))
+        .min()
+        .unwrap_or(usize::MAX);
+    if distance <= max_distance(&query) {
+        return Some(SearchMatch { kind: MatchKind::Fuzzy, score: distance });
+    }
+
+    let in_metadata = font.repo_font.values().any(|repo_font| {
+        repo_font
+            .creator
+            .iter()
+            .chain(repo_font.commentary.iter())
+            .chain(repo_font.subsets.iter().flatten())
+            .any(|text| text.to_lowercase().contains(&query))
+    });
+    if in_metadata {
+        return Some(SearchMatch { kind: MatchKind::Metadata, score: 0 });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate_fonts_list, Catalog, RepoFont};
+
+    fn catalog(families: &[&str]) -> Catalog {
+        let fonts = families
+            .iter()
+            .map(|family| RepoFont {
+                family: Some(family.to_string()),
+                ..RepoFont::default()
+            })
+            .collect();
+        let repos = vec![("Test".to_string(), fonts)].into_iter().collect();
+        generate_fonts_list(repos, Vec::new())
+    }
+
+    /// Families of `Catalog::search`, best matches first.
+    fn ranked(families: &[&str], query: &str) -> Vec<String> {
+        catalog(families)
+            .search(query)
+            .into_iter()
+            .map(Font::get_family)
+            .collect()
+    }
+
+    #[test]
+    fn levenshtein_counts_edits() {
+        assert_eq!(levenshtein("roboto", "roboto"), 0);
+        assert_eq!(levenshtein("robto", "roboto"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+
+    #[test]
+    fn typos_rank_the_closest_family_first() {
+        let families = ["Roboto Mono", "Robot Crush", "Roboto", "Lato"];
+        assert_eq!(ranked(&families, "robto")[0], "Roboto");
+        assert!(!ranked(&families, "robto").contains(&"Lato".to_string()));
+    }
+
+    #[test]
+    fn exact_matches_rank_before_prefixes_and_substrings() {
+        let families = ["Noto Sans Mono", "Noto Sans", "Open Sans", "Sans"];
+        assert_eq!(
+            ranked(&families, "sans"),
+            vec!["Sans", "Noto Sans", "Noto Sans Mono", "Open Sans"]
+        );
+        assert_eq!(ranked(&families, "noto"), vec!["Noto Sans", "Noto Sans Mono"]);
+    }
+}
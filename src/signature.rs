@@ -0,0 +1,222 @@
+use std::convert::TryInto;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str;
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{download, font_catcher_dir, Error, HttpOptions, Result};
+
+/// How strictly the detached signature of a repository catalog is checked.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Verify {
+    /// The catalog must have a valid signature from a trusted key.
+    Required,
+    /// A signature is checked when the repository publishes one.
+    Optional,
+    #[default]
+    Off,
+}
+
+pub fn trusted_keys_dir() -> Option<PathBuf> {
+    font_catcher_dir().map(|dir| dir.join("trusted_keys"))
+}
+
+/// The signature of a catalog lives next to it: `fonts.json` -> `fonts.json.sig`.
+pub fn signature_url(catalog_url: &str) -> String {
+    match catalog_url.split_once('?') {
+        Some((path, query)) => format!("{}.sig?{}", path, query),
+        None => format!("{}.sig", catalog_url),
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    // An odd length leaves a last byte `get` can't take two digits from
+    (0..text.len())
+        .step_by(2)
+        .map(|i| text.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+/// Keys are stored one per file, either as 32 raw bytes or as hex.
+fn parse_key_bytes(bytes: &[u8]) -> Option<[u8; 32]> {
+    let bytes = match str::from_utf8(bytes).ok().and_then(decode_hex) {
+        Some(decoded) => decoded,
+        None => bytes.to_vec(),
+    };
+    bytes.try_into().ok()
+}
+
+fn parse_signature_bytes(bytes: &[u8]) -> Option<Signature> {
+    let bytes = match str::from_utf8(bytes).ok().and_then(decode_hex) {
+        Some(decoded) => decoded,
+        None => bytes.to_vec(),
+    };
+    let bytes: [u8; 64] = bytes.try_into().ok()?;
+    Some(Signature::from_bytes(&bytes))
+}
+
+/// Reads the keys in `keys_dir`, returning the files that aren't ed25519
+/// public keys separately.
+fn load_trusted_keys(keys_dir: &Path) -> Result<(Vec<VerifyingKey>, Vec<PathBuf>)> {
+    let mut keys = Vec::new();
+    let mut invalid = Vec::new();
+    if !keys_dir.is_dir() {
+        return Ok((keys, invalid));
+    }
+    for entry in fs::read_dir(keys_dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        match parse_key_bytes(&fs::read(&path)?).and_then(|key| VerifyingKey::from_bytes(&key).ok())
+        {
+            Some(key) => keys.push(key),
+            None => invalid.push(path),
+        }
+    }
+    Ok((keys, invalid))
+}
+
+/// Files in `keys_dir` that aren't ed25519 public keys, which catalog
+/// verification skips.
+pub fn invalid_trusted_keys(keys_dir: &Path) -> Result<Vec<PathBuf>> {
+    Ok(load_trusted_keys(keys_dir)?.1)
+}
+
+/// Checks the catalog downloaded from `catalog_url` against its detached
+/// signature and the keys in `keys_dir`.
+pub(crate) fn verify_catalog(
+    catalog: &[u8],
+    catalog_url: &str,
+    verify: Verify,
+    keys_dir: &Path,
+    options: &HttpOptions,
+) -> Result<()> {
+    if verify == Verify::Off {
+        return Ok(());
+    }
+
+    let signature_url = signature_url(catalog_url);
+    let signature = match download(&signature_url, options) {
+        Ok(signature) => signature,
+        Err(Error::Http { status: 404, .. }) | Err(Error::NotFound(_))
+            if verify == Verify::Optional =>
+        {
+            return Ok(());
+        }
+        Err(Error::Http { status: 404, .. }) | Err(Error::NotFound(_)) => {
+            return Err(Error::InvalidSignature(format!(
+                "{} has no signature at {}",
+                catalog_url, signature_url
+            )));
+        }
+        Err(e) => return Err(e),
+    };
+    let signature = parse_signature_bytes(&signature).ok_or_else(|| {
+        Error::InvalidSignature(format!("{} is not an ed25519 signature", signature_url))
+    })?;
+
+    let (keys, _invalid) = load_trusted_keys(keys_dir)?;
+    if keys.is_empty() {
+        return Err(Error::InvalidSignature(format!(
+            "no trusted keys in {} to verify {}",
+            keys_dir.display(),
+            catalog_url
+        )));
+    }
+    if keys.iter().any(|key| key.verify(catalog, &signature).is_ok()) {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature(format!(
+            "{} is not signed by any trusted key",
+            catalog_url
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+
+    const CATALOG: &[u8] = br#"{"kind": "webfonts#webfontList", "items": []}"#;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// A catalog signed with a trusted key, returning its URL and the folder
+    /// of trusted keys.
+    fn signed_catalog(dir: &Path) -> (String, PathBuf) {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let keys_dir = dir.join("trusted_keys");
+        fs::create_dir_all(&keys_dir).unwrap();
+        fs::write(keys_dir.join("repo.pub"), hex(key.verifying_key().as_bytes())).unwrap();
+
+        let catalog = dir.join("fonts.json");
+        fs::write(&catalog, CATALOG).unwrap();
+        fs::write(dir.join("fonts.json.sig"), hex(&key.sign(CATALOG).to_bytes())).unwrap();
+        (format!("file://{}", catalog.display()), keys_dir)
+    }
+
+    fn verify(catalog: &[u8], url: &str, verify: Verify, keys_dir: &Path) -> Result<()> {
+        verify_catalog(catalog, url, verify, keys_dir, &HttpOptions::default())
+    }
+
+    #[test]
+    fn required_accepts_a_valid_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, keys_dir) = signed_catalog(dir.path());
+        assert!(verify(CATALOG, &url, Verify::Required, &keys_dir).is_ok());
+    }
+
+    #[test]
+    fn required_rejects_a_tampered_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, keys_dir) = signed_catalog(dir.path());
+        let tampered = br#"{"kind": "webfonts#webfontList", "items": [{}]}"#;
+        assert!(matches!(
+            verify(tampered, &url, Verify::Required, &keys_dir),
+            Err(Error::InvalidSignature(_))
+        ));
+    }
+
+    #[test]
+    fn missing_signature_fails_only_when_required() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, keys_dir) = signed_catalog(dir.path());
+        fs::remove_file(dir.path().join("fonts.json.sig")).unwrap();
+        assert!(matches!(
+            verify(CATALOG, &url, Verify::Required, &keys_dir),
+            Err(Error::InvalidSignature(_))
+        ));
+        assert!(verify(CATALOG, &url, Verify::Optional, &keys_dir).is_ok());
+    }
+
+    #[test]
+    fn optional_checks_a_published_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, keys_dir) = signed_catalog(dir.path());
+        assert!(verify(CATALOG, &url, Verify::Optional, &keys_dir).is_ok());
+        assert!(verify(b"tampered", &url, Verify::Optional, &keys_dir).is_err());
+    }
+
+    #[test]
+    fn off_checks_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, keys_dir) = signed_catalog(dir.path());
+        assert!(verify(b"tampered", &url, Verify::Off, &keys_dir).is_ok());
+    }
+
+    #[test]
+    fn decode_hex_rejects_odd_lengths() {
+        assert_eq!(decode_hex("0aff"), Some(vec![0x0a, 0xff]));
+        assert_eq!(decode_hex("0af"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+}
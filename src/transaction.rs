@@ -0,0 +1,219 @@
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
+
+use font_kit::font::Font as LoadedFont;
+use tempfile::TempDir;
+
+use crate::{Error, Result};
+
+/// Stages files in a hidden directory inside `target` and moves them into
+/// place only once all of them have been written and load as fonts. If
+/// anything fails, `target` is left exactly as it was.
+pub(crate) struct Transaction {
+    target: PathBuf,
+    staging: TempDir,
+    staged: Vec<(PathBuf, PathBuf)>,
+    created_target: bool,
+    committed: bool,
+}
+
+impl Transaction {
+    pub(crate) fn new(target: &Path) -> Result<Self> {
+        let created_target = !target.exists();
+        create_dir_all(target).map_err(|e| Error::with_path(e, target.to_path_buf()))?;
+        let staging = tempfile::Builder::new()
+            .prefix(".font-catcher-")
+            .tempdir_in(target)
+            .map_err(|e| Error::with_path(e, target.to_path_buf()))?;
+        Ok(Transaction {
+            target: target.to_path_buf(),
+            staging,
+            staged: Vec::new(),
+            created_target,
+            committed: false,
+        })
+    }
+
+    /// Reserves a staging path for a file that will end up as `target/file_name`.
+    pub(crate) fn stage(&mut self, file_name: &str) -> PathBuf {
+        let staged = self.staging.path().join(self.staged.len().to_string()).join(file_name);
+        self.staged
+            .push((staged.clone(), self.target.join(file_name)));
+        staged
+    }
+
+    fn verify(&self) -> Result<()> {
+        for (staged, destination) in self.staged.iter() {
+            LoadedFont::from_path(staged, 0).map_err(|e| {
+                Error::FontLoad(format!("{}: {}", destination.display(), e))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Verifies every staged file, moves them into the target directory and
+    /// calls `finish` with their final paths, which are returned. If `finish`
+    /// fails, the files are moved back out again.
+    pub(crate) fn commit(
+        mut self,
+        finish: impl FnOnce(&[PathBuf]) -> Result<()>,
+    ) -> Result<Vec<PathBuf>> {
+        self.verify()?;
+
+        let backup_dir = self.staging.path().join("backup");
+        create_dir_all(&backup_dir)?;
+
+        let mut moved: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+        for (i, (staged, destination)) in self.staged.iter().enumerate() {
+            let result = (|| -> std::io::Result<Option<PathBuf>> {
+                let backup = if destination.exists() {
+                    let backup = backup_dir.join(i.to_string());
+                    fs::rename(destination, &backup)?;
+                    Some(backup)
+                } else {
+                    None
+                };
+                match fs::rename(staged, destination) {
+                    Ok(()) => Ok(backup),
+                    Err(e) => {
+                        if let Some(backup) = &backup {
+                            let _ = fs::rename(backup, destination);
+                        }
+                        Err(e)
+                    }
+                }
+            })();
+
+            match result {
+                Ok(backup) => moved.push((destination.clone(), backup)),
+                Err(e) => {
+                    roll_back(moved);
+                    return Err(Error::with_path(e, destination.clone()));
+                }
+            }
+        }
+
+        let destinations: Vec<PathBuf> = self
+            .staged
+            .iter()
+            .map(|(_, destination)| destination.clone())
+            .collect();
+        if let Err(e) = finish(&destinations) {
+            roll_back(moved);
+            return Err(e);
+        }
+        self.committed = true;
+        Ok(destinations)
+    }
+}
+
+/// Removes the files `commit` moved into place and restores the ones they
+/// replaced, newest first.
+fn roll_back(moved: Vec<(PathBuf, Option<PathBuf>)>) {
+    for (destination, backup) in moved.into_iter().rev() {
+        let _ = fs::remove_file(&destination);
+        if let Some(backup) = backup {
+            let _ = fs::rename(backup, &destination);
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        // The staging directory has to go before the target can be removed
+        let _ = fs::remove_dir_all(self.staging.path());
+        if !self.committed && self.created_target {
+            let _ = fs::remove_dir(&self.target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::tests::system_font_file;
+
+    /// Every entry of `dir` with the content of the files in it.
+    fn snapshot(dir: &Path) -> BTreeMap<PathBuf, Option<Vec<u8>>> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let content = path.is_file().then(|| fs::read(&path).unwrap());
+                (path, content)
+            })
+            .collect()
+    }
+
+    fn stage_font(transaction: &mut Transaction, file_name: &str, font_file: &Path) {
+        let staged = transaction.stage(file_name);
+        create_dir_all(staged.parent().unwrap()).unwrap();
+        fs::copy(font_file, staged).unwrap();
+    }
+
+    #[test]
+    fn failed_commit_leaves_target_unchanged() {
+        let font_file = match system_font_file() {
+            Some(font_file) => font_file,
+            None => return,
+        };
+        let target = tempfile::tempdir().unwrap();
+        fs::write(target.path().join("Test-regular.ttf"), b"installed before").unwrap();
+        fs::write(target.path().join("Other-regular.ttf"), b"another font").unwrap();
+        let before = snapshot(target.path());
+
+        let mut transaction = Transaction::new(target.path()).unwrap();
+        stage_font(&mut transaction, "Test-regular.ttf", &font_file);
+        stage_font(&mut transaction, "Test-700.ttf", &font_file);
+        let broken = transaction.stage("Test-italic.ttf");
+        create_dir_all(broken.parent().unwrap()).unwrap();
+        fs::write(broken, b"not a font").unwrap();
+
+        let result = transaction.commit(|_paths| Ok(()));
+        assert!(matches!(result, Err(Error::FontLoad(_))));
+        assert_eq!(snapshot(target.path()), before);
+    }
+
+    #[test]
+    fn failed_finish_moves_files_back() {
+        let font_file = match system_font_file() {
+            Some(font_file) => font_file,
+            None => return,
+        };
+        let target = tempfile::tempdir().unwrap();
+        fs::write(target.path().join("Test-regular.ttf"), b"installed before").unwrap();
+        let before = snapshot(target.path());
+
+        let mut transaction = Transaction::new(target.path()).unwrap();
+        stage_font(&mut transaction, "Test-regular.ttf", &font_file);
+        stage_font(&mut transaction, "Test-700.ttf", &font_file);
+        let result = transaction.commit(|_paths| Err(Error::NotFound("manifest".to_string())));
+        assert!(matches!(result, Err(Error::NotFound(_))));
+        assert_eq!(snapshot(target.path()), before);
+    }
+
+    #[test]
+    fn commit_replaces_existing_files() {
+        let font_file = match system_font_file() {
+            Some(font_file) => font_file,
+            None => return,
+        };
+        let target = tempfile::tempdir().unwrap();
+        let existing = target.path().join("Test-regular.ttf");
+        fs::write(&existing, b"installed before").unwrap();
+
+        let mut transaction = Transaction::new(target.path()).unwrap();
+        stage_font(&mut transaction, "Test-regular.ttf", &font_file);
+        stage_font(&mut transaction, "Test-700.ttf", &font_file);
+        let written = transaction.commit(|_paths| Ok(())).unwrap();
+
+        let font = fs::read(&font_file).unwrap();
+        assert_eq!(written, vec![existing.clone(), target.path().join("Test-700.ttf")]);
+        let after = snapshot(target.path());
+        assert_eq!(after.len(), 2);
+        assert_eq!(after[&existing], Some(font.clone()));
+        assert_eq!(after[&target.path().join("Test-700.ttf")], Some(font));
+    }
+}
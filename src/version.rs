@@ -0,0 +1,214 @@
+use std::cmp::Ordering;
+use std::convert::TryInto;
+use std::path::Path;
+
+use font_kit::font::Font as LoadedFont;
+
+const NAME_TABLE: u32 = u32::from_be_bytes(*b"name");
+const HEAD_TABLE: u32 = u32::from_be_bytes(*b"head");
+const VERSION_NAME_ID: u16 = 5;
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Version string from the name table (name ID 5), e.g. `Version 2.137`.
+fn name_table_version(table: &[u8]) -> Option<String> {
+    let count = read_u16(table, 2)? as usize;
+    let storage = read_u16(table, 4)? as usize;
+    for i in 0..count {
+        let record = 6 + i * 12;
+        let platform = read_u16(table, record)?;
+        let name_id = read_u16(table, record + 6)?;
+        if name_id != VERSION_NAME_ID {
+            continue;
+        }
+        let length = read_u16(table, record + 8)? as usize;
+        let offset = storage + read_u16(table, record + 10)? as usize;
+        let bytes = table.get(offset..offset + length)?;
+        let version = match platform {
+            // Macintosh names are single byte
+            1 => bytes.iter().map(|&byte| byte as char).collect(),
+            _ => String::from_utf16_lossy(
+                &bytes
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect::<Vec<u16>>(),
+            ),
+        };
+        let version = version.trim().to_string();
+        if !version.is_empty() {
+            return Some(version);
+        }
+    }
+    None
+}
+
+/// `head.fontRevision`, a 16.16 fixed point number.
+fn head_table_version(table: &[u8]) -> Option<String> {
+    let revision = i32::from_be_bytes(table.get(4..8)?.try_into().ok()?);
+    Some(format!("{:.3}", revision as f64 / 65536.0))
+}
+
+/// Reads the version of the font stored at `path`, preferring the name table
+/// version string and falling back to `head.fontRevision`.
+pub fn font_file_version(path: &Path) -> Option<String> {
+    let font = LoadedFont::from_path(path, 0).ok()?;
+    font.load_font_table(NAME_TABLE)
+        .and_then(|table| name_table_version(&table))
+        .or_else(|| {
+            font.load_font_table(HEAD_TABLE)
+                .and_then(|table| head_table_version(&table))
+        })
+}
+
+/// First dotted version in the string: `Version 2.137;PS 2.1` -> `2.137`.
+fn dotted_version(version: &str) -> Option<(&str, &str)> {
+    let start = version.find(|c: char| c.is_ascii_digit())?;
+    let dotted = version[start..]
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .next()
+        .unwrap_or("")
+        .trim_end_matches('.');
+    Some((&version[..start], dotted))
+}
+
+/// Numbers of the first dotted version in the string: `Version 2.137;PS 2.1` -> [2, 137].
+fn version_numbers(version: &str) -> Vec<u64> {
+    match dotted_version(version) {
+        Some((_, dotted)) => dotted
+            .split('.')
+            .filter_map(|part| part.parse().ok())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// How a version string is numbered. Only versions numbered the same way
+/// can be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scheme {
+    /// Catalog revisions such as `v30` or `v3.0.2`.
+    Revision,
+    /// Font revisions such as `Version 2.137` or `2.2`, decimal numbers
+    /// where `2.2` equals `2.200`.
+    Decimal,
+    /// Other dotted versions such as `1.2.3`.
+    Dotted,
+}
+
+fn version_scheme(prefix: &str, dotted: &str) -> Scheme {
+    let mut prefix = prefix.chars().rev();
+    let revision = matches!(prefix.next(), Some('v') | Some('V'))
+        && !prefix.next().is_some_and(char::is_alphabetic);
+    if revision {
+        Scheme::Revision
+    } else if dotted.matches('.').count() <= 1 {
+        Scheme::Decimal
+    } else {
+        Scheme::Dotted
+    }
+}
+
+/// Compares two version strings numbered the same way: catalog revisions
+/// and dotted versions number by number, so `v30` > `v9`, and font
+/// revisions as decimals, so `Version 2.137` > `2.1` and `2.2` = `2.200`.
+/// Returns `None` when either has no numbers or they are numbered
+/// differently, like `Version 2.37` and `v10`.
+pub fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    let (a_prefix, a_dotted) = dotted_version(a)?;
+    let (b_prefix, b_dotted) = dotted_version(b)?;
+    let scheme = version_scheme(a_prefix, a_dotted);
+    if scheme != version_scheme(b_prefix, b_dotted) {
+        return None;
+    }
+    if scheme == Scheme::Decimal {
+        let split = |dotted: &str| {
+            let (whole, fraction) = dotted.split_once('.').unwrap_or((dotted, ""));
+            (whole.parse::<u64>().ok(), fraction.trim_end_matches('0').to_string())
+        };
+        let (a_whole, a_fraction) = split(a_dotted);
+        let (b_whole, b_fraction) = split(b_dotted);
+        // Fraction digits without trailing zeros sort like the decimals they are
+        return Some(a_whole?.cmp(&b_whole?).then(a_fraction.cmp(&b_fraction)));
+    }
+    let a = version_numbers(a);
+    let b = version_numbers(b);
+    let length = a.len().max(b.len());
+    let pad = |mut numbers: Vec<u64>| {
+        numbers.resize(length, 0);
+        numbers
+    };
+    Some(pad(a).cmp(&pad(b)))
+}
+
+/// Whether `version` meets `requirement`, a comma separated list of
+/// comparisons such as `>= v30` or `>=2.0, <3`. A version without operator
+/// has to be equal, and `*` matches anything. Versions that can't be
+/// compared never match.
+pub fn version_matches(version: &str, requirement: &str) -> bool {
+    requirement.split(',').map(str::trim).all(|comparison| {
+        if comparison.is_empty() || comparison == "*" {
+            return true;
+        }
+        let (wanted, expected): (&str, &[Ordering]) =
+            if let Some(rest) = comparison.strip_prefix(">=") {
+                (rest, &[Ordering::Greater, Ordering::Equal])
+            } else if let Some(rest) = comparison.strip_prefix("<=") {
+                (rest, &[Ordering::Less, Ordering::Equal])
+            } else if let Some(rest) = comparison.strip_prefix('>') {
+                (rest, &[Ordering::Greater])
+            } else if let Some(rest) = comparison.strip_prefix('<') {
+                (rest, &[Ordering::Less])
+            } else {
+                let rest = comparison.trim_start_matches('=');
+                (rest, &[Ordering::Equal])
+            };
+        compare_versions(version, wanted.trim())
+            .is_some_and(|ordering| expected.contains(&ordering))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_numbers_reads_the_first_dotted_version() {
+        assert_eq!(version_numbers("Version 2.137;PS 2.1"), vec![2, 137]);
+        assert_eq!(version_numbers("v30"), vec![30]);
+        assert_eq!(version_numbers("1.2.3"), vec![1, 2, 3]);
+        assert_eq!(version_numbers("unknown"), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn compare_versions_within_a_scheme() {
+        assert_eq!(compare_versions("v30", "v9"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("v3.0.2", "v3.0.10"), Some(Ordering::Less));
+        assert_eq!(compare_versions("Version 2.137", "2.1"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("2.2", "2.200"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("2.05", "2.5"), Some(Ordering::Less));
+        assert_eq!(compare_versions("Version 2.37", "2.200"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("1.10.0", "1.9.1"), Some(Ordering::Greater));
+    }
+
+    #[test]
+    fn compare_versions_across_schemes() {
+        assert_eq!(compare_versions("Version 2.37", "v10"), None);
+        assert_eq!(compare_versions("v10", "2.37"), None);
+        assert_eq!(compare_versions("1.2.3", "1.2"), None);
+        assert_eq!(compare_versions("latest", "v10"), None);
+    }
+
+    #[test]
+    fn version_matches_requirements() {
+        assert!(version_matches("v30", ">= v30"));
+        assert!(version_matches("v31", ">v30, <v40"));
+        assert!(!version_matches("v40", ">v30, <v40"));
+        assert!(version_matches("Version 2.37", ">= 2.2"));
+        assert!(version_matches("2.200", "2.2"));
+        assert!(version_matches("anything", "*"));
+        assert!(!version_matches("Version 2.37", ">= v10"));
+    }
+}